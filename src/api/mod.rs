@@ -1,3 +1,3 @@
 mod open_weather_api;
 
-pub use open_weather_api::{WeatherData, get_weather};
+pub use open_weather_api::{ApiError, WeatherData, get_weather};
@@ -1,8 +1,13 @@
 use std::error::Error;
+use std::fmt;
 use std::time::Duration;
 
+use reqwest::StatusCode;
+
 use serde_derive::{Deserialize, Serialize};
 
+use crate::open_metrics::{MetricType, OpenMetrics, OpenMetricsWriter};
+
 // Docs are at https://openweathermap.org/current#current_JSON. Internal parameters are excluded, as they might not
 // exist.
 
@@ -70,8 +75,51 @@ pub struct WeatherData {
   pub name: String,
 }
 
+impl OpenMetrics for WeatherData {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    let labels = [("city", self.name.as_str())];
+    writer
+      .family(MetricType::Gauge, "temperature", Some("celsius"), "Outside temperature")
+      .sample(&labels, self.main.temp as f64);
+    writer
+      .family(MetricType::Gauge, "humidity", Some("ratio"), "Relative humidity")
+      .sample(&labels, self.main.humidity as f64 / 100.0);
+    writer
+      .family(
+        MetricType::Gauge,
+        "pressure",
+        Some("pascals"),
+        "Atmospheric pressure at sea level",
+      )
+      .sample(&labels, self.main.pressure as f64 * 100.0);
+    writer
+      .family(MetricType::Gauge, "wind_speed", Some("meters_per_second"), "Wind speed")
+      .sample(&labels, self.wind.speed as f64);
+  }
+}
+
 static REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// A response other than a success, with *OpenWeather*'s message
+#[derive(Debug)]
+pub struct ApiError {
+  pub status: StatusCode,
+  pub message: String,
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.status, self.message)
+  }
+}
+
+impl Error for ApiError {}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+  message: String,
+}
+
 pub async fn get_weather(city_id: u64, api_key: &str) -> Result<WeatherData, Box<dyn Error>> {
   let request_url = format!(
     "https://api.openweathermap.org/data/2.5/weather?id={city_id}&units={units}&APPID={api_key}",
@@ -80,7 +128,18 @@ pub async fn get_weather(city_id: u64, api_key: &str) -> Result<WeatherData, Box
     api_key = api_key
   );
   let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
-  let response = client.get(&request_url).send().await?;
-  let res: WeatherData = serde_json::from_str(&response.text().await?)?;
+  // The URL has the API key in it, so leave it out of errors
+  let response = client
+    .get(&request_url)
+    .send()
+    .await
+    .map_err(reqwest::Error::without_url)?;
+  let status = response.status();
+  let text = response.text().await.map_err(reqwest::Error::without_url)?;
+  if !status.is_success() {
+    let message = serde_json::from_str::<ApiErrorBody>(&text).map_or(text, |body| body.message);
+    return Err(ApiError { status, message }.into());
+  }
+  let res: WeatherData = serde_json::from_str(&text)?;
   Ok(res)
 }
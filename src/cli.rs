@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::control::Message;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+  /// Reads `dogky.yaml` from this path instead
+  #[arg(long, global = true, value_name = "PATH")]
+  pub config: Option<PathBuf>,
+  /// Reads `styles.yaml` from this path instead
+  #[arg(long, global = true, value_name = "PATH")]
+  pub styles: Option<PathBuf>,
+  /// Reads `dogky.NAME.yaml` and `styles.NAME.yaml`, falling back to `styles.yaml`. Also sets the window title and the
+  /// control socket. One instance runs per profile.
+  #[arg(long, global = true, value_name = "NAME")]
+  pub profile: Option<String>,
+  /// Opens the window when left out
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+  /// Validates `dogky.yaml` and `styles.yaml`, without opening a window. Exits with 1 when either is invalid.
+  CheckConfig,
+  /// Prints a default config file, with every option commented.
+  PrintDefaultConfig {
+    #[arg(value_enum, default_value_t = ConfigFile::Config)]
+    file: ConfigFile,
+  },
+  /// Prints the JSON Schema of a config file, e.g. for `yaml-language-server`.
+  Schema {
+    #[arg(value_enum, default_value_t = ConfigFile::Config)]
+    file: ConfigFile,
+  },
+  /// Prints the metrics of the panels in `dogky.yaml` as JSON, without opening a window.
+  Dump {
+    /// Prints a line of JSON every this many seconds, instead of a single document
+    #[arg(long, value_name = "SECS")]
+    interval: Option<u64>,
+  },
+  /// Sends a message to the running instance, through its socket under `$XDG_RUNTIME_DIR/dogky/`.
+  Msg {
+    #[command(subcommand)]
+    message: Message,
+  },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ConfigFile {
+  /// `dogky.yaml`
+  Config,
+  /// `styles.yaml`
+  Styles,
+}
+
+pub const DEFAULT_CONFIG: &str = include_str!("default_config/dogky.yaml");
+pub const DEFAULT_STYLES: &str = include_str!("default_config/styles.yaml");
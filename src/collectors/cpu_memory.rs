@@ -0,0 +1,548 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use binary_heap_plus::BinaryHeap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::Serialize;
+use sysinfo::{
+  Components, CpuRefreshKind, MemoryRefreshKind, Pid, ProcessRefreshKind, ProcessStatus, ProcessesToUpdate,
+  RefreshKind, System, UpdateKind,
+};
+
+use super::{Collector, read_temperature};
+use crate::config::{CpuMemoryConfig, TemperatureSensor};
+use crate::cpu_cores::{CoreKind, get_core_kinds};
+use crate::open_metrics::{MetricType, OpenMetrics, OpenMetricsWriter};
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct CpuSample {
+  /// GHz
+  pub frequency: f32,
+  /// °C. `None` when the sensor can't be read.
+  pub temperature: Option<f32>,
+  /// Percent
+  pub usage: f32,
+  pub core_usage: Vec<f32>,
+  /// Of each core in `core_usage`
+  pub core_kinds: Vec<CoreKind>,
+  pub times: CpuTimes,
+  /// Seconds
+  pub uptime: u64,
+}
+
+/// Shares of the CPU time since the previous sample, in percent. `user` includes `nice`, and `irq` includes `softirq`.
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct CpuTimes {
+  pub user: f32,
+  pub system: f32,
+  pub iowait: f32,
+  pub irq: f32,
+  pub steal: f32,
+}
+
+impl CpuTimes {
+  pub const NAMES: [&str; 5] = ["user", "system", "iowait", "irq", "steal"];
+
+  /// In the order of `NAMES`
+  pub fn shares(&self) -> [f32; 5] {
+    [self.user, self.system, self.iowait, self.irq, self.steal]
+  }
+
+  fn from_ticks(ticks: &CpuTicks, previous: &CpuTicks) -> Self {
+    let delta = |i: usize| ticks[i].saturating_sub(previous[i]) as f32;
+    let total: f32 = (0..ticks.len()).map(delta).sum();
+    if total == 0. {
+      return CpuTimes::default();
+    }
+    let share = |ticks: f32| ticks / total * 100.;
+    CpuTimes {
+      user: share(delta(0) + delta(1)),
+      system: share(delta(2)),
+      iowait: share(delta(4)),
+      irq: share(delta(5) + delta(6)),
+      steal: share(delta(7)),
+    }
+  }
+}
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct MemorySample {
+  /// Bytes, like the rest
+  pub memory_usage: u64,
+  pub memory_total: u64,
+  pub swap_usage: u64,
+  pub swap_total: u64,
+}
+
+#[derive(Default, Clone, Serialize)]
+pub struct ProcessesSample {
+  pub top_cpu: Vec<ProcessSample>,
+  pub top_memory: Vec<ProcessSample>,
+  pub num_total: usize,
+  pub num_running: usize,
+}
+
+impl OpenMetrics for CpuSample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    writer
+      .family(
+        MetricType::Gauge,
+        "frequency",
+        Some("hertz"),
+        "Frequency of the first core",
+      )
+      .sample(&[], self.frequency as f64 * 1e9);
+    if let Some(temperature) = self.temperature {
+      writer
+        .family(MetricType::Gauge, "temperature", Some("celsius"), "CPU temperature")
+        .sample(&[], temperature as f64);
+    }
+    writer
+      .family(MetricType::Gauge, "usage", Some("ratio"), "Usage of all cores")
+      .sample(&[], self.usage as f64 / 100.0);
+    let family = writer.family(MetricType::Gauge, "core_usage", Some("ratio"), "Usage by core");
+    for (core, (usage, kind)) in self.core_usage.iter().zip(&self.core_kinds).enumerate() {
+      family.sample(
+        &[("core", &core.to_string()), ("kind", kind.name())],
+        *usage as f64 / 100.0,
+      );
+    }
+    let family = writer.family(
+      MetricType::Gauge,
+      "time",
+      Some("ratio"),
+      "Share of the CPU time since the previous sample, by mode",
+    );
+    for (mode, share) in CpuTimes::NAMES.iter().zip(self.times.shares()) {
+      family.sample(&[("mode", mode)], share as f64 / 100.0);
+    }
+    writer
+      .family(MetricType::Gauge, "uptime", Some("seconds"), "System uptime")
+      .sample(&[], self.uptime as f64);
+  }
+}
+
+impl OpenMetrics for MemorySample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    writer
+      .family(MetricType::Gauge, "used", Some("bytes"), "Used memory")
+      .sample(&[], self.memory_usage as f64);
+    writer
+      .family(MetricType::Gauge, "size", Some("bytes"), "Total memory")
+      .sample(&[], self.memory_total as f64);
+    writer
+      .family(MetricType::Gauge, "swap_used", Some("bytes"), "Used swap")
+      .sample(&[], self.swap_usage as f64);
+    writer
+      .family(MetricType::Gauge, "swap_size", Some("bytes"), "Total swap")
+      .sample(&[], self.swap_total as f64);
+  }
+}
+
+/// Only the counts, the top processes would make a label of every PID
+impl OpenMetrics for ProcessesSample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    writer
+      .family(MetricType::Gauge, "count", None, "Number of processes")
+      .sample(&[], self.num_total as f64);
+    writer
+      .family(MetricType::Gauge, "running", None, "Number of running processes")
+      .sample(&[], self.num_running as f64);
+  }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ProcessSample {
+  pub cmd: String,
+  pub pid: u32,
+  /// Percent of one core
+  pub cpu_usage: f32,
+  /// Bytes
+  pub memory_usage: u64,
+}
+
+#[derive(Debug)]
+enum TemperatureSource {
+  /// The first sensor with a matching label
+  Label(Regex),
+  /// Millidegrees Celsius
+  Path(PathBuf),
+}
+
+lazy_static! {
+  /// Tried in order when no sensor is configured
+  static ref RE_SENSOR_LABELS: Vec<Regex> = [
+    // Intel
+    r"^coretemp Package id 0$",
+    // AMD. Tctl is what fans are controlled by, Tccd1 the first chiplet.
+    r"^k10temp Tctl$",
+    r"^k10temp Tdie$",
+    r"^k10temp Tccd1$",
+    r"^zenpower Tdie$",
+    // ARM, thermal zones exposed through hwmon
+    r"^(cpu|soc)\w*[_-]thermal\b",
+  ]
+  .iter()
+  .map(|label| Regex::new(label).unwrap())
+  .collect();
+  /// Thermal zones of CPUs, for when there's no *hwmon* driver for them, e.g. on *ARM* boards
+  static ref RE_THERMAL_ZONE_TYPE: Regex = Regex::new(r"^(cpu|soc|x86_pkg_temp)").unwrap();
+}
+
+fn find_thermal_zone() -> Option<PathBuf> {
+  let mut zones: Vec<PathBuf> = std::fs::read_dir("/sys/class/thermal")
+    .ok()?
+    .filter_map(|entry| Some(entry.ok()?.path()))
+    .filter(|path| {
+      path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone"))
+    })
+    .collect();
+  zones.sort();
+  zones
+    .into_iter()
+    .find(|zone| {
+      std::fs::read_to_string(zone.join("type")).is_ok_and(|zone_type| RE_THERMAL_ZONE_TYPE.is_match(zone_type.trim()))
+    })
+    .map(|zone| zone.join("temp"))
+}
+
+/// For error messages
+fn get_sensor_labels(components: &Components) -> String {
+  if components.is_empty() {
+    return "none".to_string();
+  }
+  components
+    .iter()
+    .map(|component| component.label())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn detect_temperature_source(components: &Components) -> Result<TemperatureSource, String> {
+  RE_SENSOR_LABELS
+    .iter()
+    .find(|regex| components.iter().any(|component| regex.is_match(component.label())))
+    .map(|regex| TemperatureSource::Label(regex.clone()))
+    .or_else(|| find_thermal_zone().map(TemperatureSource::Path))
+    .ok_or_else(|| {
+      format!(
+        "No CPU temperature sensor found. Set cpu_memory.temperature_sensor, the labels are: {}",
+        get_sensor_labels(components)
+      )
+    })
+}
+
+const PROC_STAT_PATH: &str = "/proc/stat";
+
+/// User, nice, system, idle, iowait, irq, softirq and steal time of all CPUs, in clock ticks. Guest time is already
+/// counted in user time.
+type CpuTicks = [u64; 8];
+
+fn read_cpu_ticks() -> Result<CpuTicks, Box<dyn Error>> {
+  let stat = std::fs::read_to_string(PROC_STAT_PATH)?;
+  let line = stat
+    .lines()
+    .find(|line| line.starts_with("cpu "))
+    .ok_or_else(|| format!("No cpu line in {}", PROC_STAT_PATH))?;
+  let ticks = line
+    .split_whitespace()
+    .skip(1)
+    .take(8)
+    .map(str::parse)
+    .collect::<Result<Vec<u64>, _>>()?;
+  Ok(
+    ticks
+      .try_into()
+      .map_err(|_| format!("Too few fields on the cpu line of {}", PROC_STAT_PATH))?,
+  )
+}
+
+pub struct CpuCollector {
+  system: System,
+  components: Components,
+  /// Detected on the first sample when not configured
+  temperature_source: Option<TemperatureSource>,
+  /// Logged when it changes, rather than on every sample
+  temperature_error: Option<String>,
+  core_kinds: Vec<CoreKind>,
+  /// As of the previous sample, or of creating the collector. `None` when they couldn't be read.
+  ticks: Option<CpuTicks>,
+  interval: Duration,
+}
+
+impl CpuCollector {
+  pub fn new(config: &CpuMemoryConfig) -> Self {
+    let system = System::new_with_specifics(RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing()));
+    CpuCollector {
+      core_kinds: get_core_kinds(system.cpus().len()),
+      // Otherwise the first sample would share out the time since boot
+      ticks: read_cpu_ticks().ok(),
+      system,
+      components: Components::new(),
+      temperature_source: config.temperature_sensor.as_ref().map(|sensor| match sensor {
+        TemperatureSensor::Label(label) => TemperatureSource::Label((**label).clone()),
+        TemperatureSensor::Path(path) => TemperatureSource::Path(PathBuf::from(path)),
+      }),
+      temperature_error: None,
+      interval: Duration::from_secs(config.update_interval),
+    }
+  }
+
+  fn get_temperature(&mut self) -> Result<f32, Box<dyn Error>> {
+    self.components.refresh(true);
+    let source = match &self.temperature_source {
+      Some(source) => source,
+      None => self
+        .temperature_source
+        .insert(detect_temperature_source(&self.components)?),
+    };
+    match source {
+      TemperatureSource::Label(regex) => {
+        let component = self
+          .components
+          .iter()
+          .find(|component| regex.is_match(component.label()))
+          .ok_or_else(|| {
+            format!(
+              "No temperature sensor label matches {}, the labels are: {}",
+              regex,
+              get_sensor_labels(&self.components)
+            )
+          })?;
+        let temperature = component
+          .temperature()
+          .ok_or_else(|| format!("No temperature from {}", component.label()))?;
+        Ok(temperature)
+      }
+      TemperatureSource::Path(path) => read_temperature(path),
+    }
+  }
+}
+
+impl Collector for CpuCollector {
+  type Sample = CpuSample;
+  const NAME: &'static str = "cpu";
+
+  fn key(&self) -> String {
+    format!("{}:{:?}:{:?}", Self::NAME, self.temperature_source, self.interval)
+  }
+
+  fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  fn collect(&mut self) -> Result<CpuSample, Box<dyn Error>> {
+    let mut res = CpuSample::default();
+    self
+      .system
+      .refresh_cpu_specifics(CpuRefreshKind::nothing().with_frequency().with_cpu_usage());
+
+    // The rest of the sample doesn't depend on it
+    res.temperature = match self.get_temperature() {
+      Ok(temperature) => {
+        self.temperature_error = None;
+        Some(temperature)
+      }
+      Err(err) => {
+        let err = err.to_string();
+        if self.temperature_error.as_ref() != Some(&err) {
+          eprintln!("Failed to read the CPU temperature: {}", err);
+        }
+        self.temperature_error = Some(err);
+        None
+      }
+    };
+    let ticks = read_cpu_ticks()?;
+    if let Some(previous) = &self.ticks {
+      res.times = CpuTimes::from_ticks(&ticks, previous);
+    }
+    self.ticks = Some(ticks);
+
+    let cpus = self.system.cpus();
+    res.frequency = cpus.first().ok_or("No CPUs found")?.frequency() as f32 / 1000.0;
+
+    res.usage = self.system.global_cpu_usage();
+    res.core_usage = cpus.iter().map(|cpu| cpu.cpu_usage()).collect();
+    res.core_kinds = self.core_kinds.clone();
+    res.uptime = System::uptime();
+    Ok(res)
+  }
+}
+
+pub struct MemoryCollector {
+  system: System,
+  interval: Duration,
+}
+
+impl MemoryCollector {
+  pub fn new(interval: Duration) -> Self {
+    MemoryCollector {
+      system: System::new_with_specifics(
+        RefreshKind::nothing().with_memory(MemoryRefreshKind::nothing().with_ram().with_swap()),
+      ),
+      interval,
+    }
+  }
+}
+
+impl Collector for MemoryCollector {
+  type Sample = MemorySample;
+  const NAME: &'static str = "memory";
+
+  fn key(&self) -> String {
+    format!("{}:{:?}", Self::NAME, self.interval)
+  }
+
+  fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  fn collect(&mut self) -> Result<MemorySample, Box<dyn Error>> {
+    self.system.refresh_memory();
+    Ok(MemorySample {
+      memory_usage: self.system.used_memory(),
+      memory_total: self.system.total_memory(),
+      swap_usage: self.system.used_swap(),
+      swap_total: self.system.total_swap(),
+    })
+  }
+}
+
+/// The processes using the most CPU and memory
+pub struct ProcessCollector {
+  system: System,
+  num_top_processes: usize,
+  interval: Duration,
+}
+
+impl ProcessCollector {
+  pub fn new(num_top_processes: usize, interval: Duration) -> Self {
+    ProcessCollector {
+      system: System::new(),
+      num_top_processes,
+      interval,
+    }
+  }
+}
+
+impl Collector for ProcessCollector {
+  type Sample = ProcessesSample;
+  const NAME: &'static str = "processes";
+
+  fn key(&self) -> String {
+    format!("{}:{}:{:?}", Self::NAME, self.num_top_processes, self.interval)
+  }
+
+  fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  fn collect(&mut self) -> Result<ProcessesSample, Box<dyn Error>> {
+    let system = &mut self.system;
+    let num_top_processes = self.num_top_processes;
+    let mut res = ProcessesSample::default();
+    system.refresh_processes_specifics(
+      ProcessesToUpdate::All,
+      true,
+      ProcessRefreshKind::nothing()
+        .with_memory()
+        .with_cpu()
+        .with_cmd(UpdateKind::Always),
+    );
+    let pid_to_process = system.processes();
+    let mut task_pids: HashSet<Pid> = HashSet::new();
+    for process in pid_to_process.values() {
+      if let Some(tasks) = process.tasks() {
+        task_pids.extend(tasks)
+      }
+    }
+    let capacity = num_top_processes + 1;
+    let mut top_cpu_id_usage =
+      BinaryHeap::with_capacity_by(capacity, |p1: &(Pid, f32), p2: &(Pid, f32)| p2.1.total_cmp(&p1.1));
+    let mut top_memory_id_usage =
+      BinaryHeap::with_capacity_by(capacity, |p1: &(Pid, u64), p2: &(Pid, u64)| p2.1.cmp(&p1.1));
+    for (pid, process) in pid_to_process {
+      if task_pids.contains(pid) {
+        continue;
+      }
+      if process.status() == ProcessStatus::Run {
+        res.num_running += 1;
+      }
+      if let Some(live_process) = system.process(*pid) {
+        res.num_total += 1;
+        top_cpu_id_usage.push((*pid, live_process.cpu_usage()));
+        top_memory_id_usage.push((*pid, live_process.memory()));
+        if top_cpu_id_usage.len() > num_top_processes {
+          top_cpu_id_usage.pop();
+          top_memory_id_usage.pop();
+        }
+      }
+    }
+    let pid_to_props = |pid| {
+      let process = pid_to_process.get(pid)?;
+      let args = process
+        .cmd()
+        .iter()
+        .skip(1)
+        .fold(String::new(), |res, cur| res + &cur.to_string_lossy() + " ");
+      Some(ProcessSample {
+        cmd: format!("{} {}", process.name().to_string_lossy(), args),
+        pid: pid.as_u32(),
+        cpu_usage: process.cpu_usage(),
+        memory_usage: process.memory(),
+      })
+    };
+    let top_cpu_vec = top_cpu_id_usage.into_sorted_vec();
+    res.top_cpu = top_cpu_vec.iter().filter_map(|(pid, _)| pid_to_props(pid)).collect();
+    let top_memory_vec = top_memory_id_usage.into_sorted_vec();
+    res.top_memory = top_memory_vec.iter().filter_map(|(pid, _)| pid_to_props(pid)).collect();
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cpu_times_are_shares_of_the_ticks_since_the_previous_sample() {
+    let previous = [100, 10, 50, 1000, 5, 1, 1, 0];
+    // 40 user, 10 nice, 20 system, 120 idle, 5 iowait, 2 irq, 3 softirq
+    let ticks = [140, 20, 70, 1120, 10, 3, 4, 0];
+    let times = CpuTimes::from_ticks(&ticks, &previous);
+    assert_eq!(times.shares(), [25., 10., 2.5, 2.5, 0.]);
+  }
+
+  #[test]
+  fn cpu_times_are_zero_without_new_ticks() {
+    let ticks = [100, 10, 50, 1000, 5, 1, 1, 0];
+    assert_eq!(CpuTimes::from_ticks(&ticks, &ticks).shares(), [0.; 5]);
+  }
+
+  #[test]
+  fn cpu_times_ignore_counters_going_backwards() {
+    let previous = [100, 0, 0, 100, 0, 0, 0, 50];
+    let ticks = [150, 0, 0, 150, 0, 0, 0, 0];
+    assert_eq!(CpuTimes::from_ticks(&ticks, &previous).shares(), [50., 0., 0., 0., 0.]);
+  }
+
+  #[test]
+  fn unknown_cpu_temperatures_are_left_out_of_the_exported_metrics() {
+    let exported = |temperature| {
+      let mut writer = OpenMetricsWriter::new(CpuCollector::NAME);
+      CpuSample {
+        temperature,
+        ..CpuSample::default()
+      }
+      .write_open_metrics(&mut writer);
+      writer.into_text()
+    };
+    assert!(exported(Some(45.)).contains("cpu_temperature_celsius 45"));
+    assert!(!exported(None).contains("temperature"));
+  }
+}
@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use serde_derive::Serialize;
+use sysinfo::{Disk, DiskRefreshKind, Disks};
+
+use super::{Collector, read_temperature};
+use crate::config::DiskConfig;
+use crate::open_metrics::{MetricType, OpenMetrics, OpenMetricsWriter};
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct DiskSample {
+  pub mount_point: String,
+  /// °C
+  pub temperature: f32,
+  /// Bytes, like the rest
+  pub available_space: u64,
+  pub total_space: u64,
+}
+
+impl OpenMetrics for DiskSample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    let labels = [("mount_point", self.mount_point.as_str())];
+    writer
+      .family(MetricType::Gauge, "temperature", Some("celsius"), "Drive temperature")
+      .sample(&labels, self.temperature as f64);
+    writer
+      .family(
+        MetricType::Gauge,
+        "available",
+        Some("bytes"),
+        "Space available to unprivileged users",
+      )
+      .sample(&labels, self.available_space as f64);
+    writer
+      .family(MetricType::Gauge, "size", Some("bytes"), "Size of the filesystem")
+      .sample(&labels, self.total_space as f64);
+  }
+}
+
+pub fn get_disk<'a>(disks: &'a Disks, mount_point: &str) -> Result<&'a Disk, String> {
+  disks
+    .into_iter()
+    .find(|disk| disk.mount_point() == Path::new(mount_point))
+    .ok_or_else(|| format!("Nothing is mounted at {}", mount_point))
+}
+
+pub struct DiskCollector {
+  disks: Disks,
+  temperature_path: String,
+  mount_point: String,
+  interval: Duration,
+}
+
+impl DiskCollector {
+  pub fn new(config: &DiskConfig) -> Self {
+    DiskCollector {
+      disks: Disks::new_with_refreshed_list_specifics(DiskRefreshKind::nothing().with_storage()),
+      temperature_path: config.temperature_path.clone(),
+      mount_point: config.mount_point.clone(),
+      interval: Duration::from_secs(config.update_interval),
+    }
+  }
+}
+
+impl Collector for DiskCollector {
+  type Sample = DiskSample;
+  const NAME: &'static str = "disk";
+
+  fn key(&self) -> String {
+    format!(
+      "{}:{}:{}:{:?}",
+      Self::NAME,
+      self.mount_point,
+      self.temperature_path,
+      self.interval
+    )
+  }
+
+  fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  fn collect(&mut self) -> Result<DiskSample, Box<dyn Error>> {
+    self
+      .disks
+      .refresh_specifics(true, DiskRefreshKind::nothing().with_storage());
+    let disk = get_disk(&self.disks, &self.mount_point)?;
+    Ok(DiskSample {
+      mount_point: self.mount_point.clone(),
+      temperature: read_temperature(Path::new(&self.temperature_path))?,
+      available_space: disk.available_space(),
+      total_space: disk.total_space(),
+    })
+  }
+}
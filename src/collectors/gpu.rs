@@ -0,0 +1,84 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use nvml_wrapper::enum_wrappers::device::{Clock, ClockId, TemperatureSensor};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, Nvml};
+use serde_derive::Serialize;
+
+use super::Collector;
+use crate::open_metrics::{MetricType, OpenMetrics, OpenMetricsWriter};
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct GpuSample {
+  /// °C
+  pub temperature: u32,
+  /// Percent
+  pub utilization_rates: u32,
+  /// MHz
+  pub gpu_frequency: u32,
+  pub memory_frequency: u32,
+  /// Bytes
+  pub memory_used: u64,
+}
+
+impl OpenMetrics for GpuSample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    writer
+      .family(MetricType::Gauge, "temperature", Some("celsius"), "GPU temperature")
+      .sample(&[], self.temperature as f64);
+    writer
+      .family(MetricType::Gauge, "utilization", Some("ratio"), "GPU utilization")
+      .sample(&[], self.utilization_rates as f64 / 100.0);
+    writer
+      .family(MetricType::Gauge, "frequency", Some("hertz"), "Graphics clock")
+      .sample(&[], self.gpu_frequency as f64 * 1e6);
+    writer
+      .family(MetricType::Gauge, "memory_frequency", Some("hertz"), "Memory clock")
+      .sample(&[], self.memory_frequency as f64 * 1e6);
+    writer
+      .family(MetricType::Gauge, "memory_used", Some("bytes"), "Used GPU memory")
+      .sample(&[], self.memory_used as f64);
+  }
+}
+
+pub fn get_gpu(nvml: &Nvml) -> Result<Device<'_>, NvmlError> {
+  nvml.device_by_index(0)
+}
+
+/// The first *NVIDIA* GPU
+pub struct GpuCollector {
+  nvml: Arc<Nvml>,
+  interval: Duration,
+}
+
+impl GpuCollector {
+  pub fn new(nvml: Arc<Nvml>, interval: Duration) -> Self {
+    GpuCollector { nvml, interval }
+  }
+}
+
+impl Collector for GpuCollector {
+  type Sample = GpuSample;
+  const NAME: &'static str = "gpu";
+
+  fn key(&self) -> String {
+    format!("{}:{:?}", Self::NAME, self.interval)
+  }
+
+  fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  fn collect(&mut self) -> Result<GpuSample, Box<dyn Error>> {
+    let gpu = get_gpu(&self.nvml)?;
+    Ok(GpuSample {
+      temperature: gpu.temperature(TemperatureSensor::Gpu)?,
+      utilization_rates: gpu.utilization_rates()?.gpu,
+      gpu_frequency: gpu.clock(Clock::Graphics, ClockId::Current)?,
+      memory_frequency: gpu.clock(Clock::Memory, ClockId::Current)?,
+      memory_used: gpu.memory_info()?.used,
+    })
+  }
+}
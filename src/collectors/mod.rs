@@ -0,0 +1,218 @@
+mod cpu_memory;
+mod disk;
+mod gpu;
+mod network;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::metrics::Metrics;
+use crate::open_metrics::OpenMetrics;
+pub use cpu_memory::{
+  CpuCollector, CpuSample, CpuTimes, MemoryCollector, MemorySample, ProcessCollector, ProcessSample, ProcessesSample,
+};
+pub use disk::{DiskCollector, DiskSample, get_disk};
+pub use gpu::{GpuCollector, GpuSample, get_gpu};
+pub use network::{NetworkCollector, NetworkSample};
+
+/// Gathers one kind of metric, independently of the UI. Each collector runs on its own thread.
+pub trait Collector: Send + 'static {
+  type Sample: Clone + Serialize + OpenMetrics + Send + Sync + 'static;
+
+  /// Listed under this name by `dogky msg metrics`, and prefixes its exported metrics
+  const NAME: &'static str;
+
+  /// Collectors with the same key are the same, so one of them serves every subscriber. Include the config in it.
+  fn key(&self) -> String;
+
+  fn interval(&self) -> Duration;
+
+  fn collect(&mut self) -> Result<Self::Sample, Box<dyn Error>>;
+}
+
+/// Reads a *hwmon* style file, in millidegrees Celsius
+fn read_temperature(path: &Path) -> Result<f32, Box<dyn Error>> {
+  let contents = std::fs::read_to_string(path)
+    .map_err(|err| format!("Failed to read the temperature from {}: {}", path.display(), err))?;
+  Ok(contents.trim().parse::<i32>()? as f32 / 1000.0)
+}
+
+/// Collects a sample, turning a panic into an error so that the collector keeps going
+pub fn try_collect<C: Collector>(collector: &mut C) -> Result<C::Sample, String> {
+  match panic::catch_unwind(AssertUnwindSafe(|| collector.collect())) {
+    Ok(sample) => sample.map_err(|err| err.to_string()),
+    Err(payload) => Err(
+      payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "The collector panicked".to_string()),
+    ),
+  }
+}
+
+/// The latest sample or why it failed, `None` until the first one. Wait for the next with `changed`.
+pub type Samples<T> = watch::Receiver<Option<Result<T, String>>>;
+
+/// Calls `f` with the latest sample, if any, then with each new one. Returns once the collector stops.
+pub async fn for_each_sample<T: Clone>(mut samples: Samples<T>, mut f: impl FnMut(Result<T, String>)) {
+  loop {
+    if let Some(sample) = samples.borrow_and_update().clone() {
+      f(sample);
+    }
+    if samples.changed().await.is_err() {
+      return;
+    }
+  }
+}
+
+/// A collector's name, and its `watch::Sender<Option<Result<Collector::Sample, String>>>`
+type Channel = (&'static str, Box<dyn Any + Send>);
+
+/// Runs collectors for the panels that subscribe to them. A collector stops once nothing is subscribed to it.
+#[derive(Clone)]
+pub struct MetricsBus {
+  /// By collector key
+  senders: Arc<Mutex<HashMap<String, Channel>>>,
+  metrics: Metrics,
+}
+
+impl MetricsBus {
+  pub fn new(metrics: Metrics) -> Self {
+    MetricsBus {
+      senders: Arc::default(),
+      metrics,
+    }
+  }
+
+  /// Starts the collector, unless one with the same key runs already.
+  pub fn subscribe<C: Collector>(&self, collector: C) -> Samples<C::Sample> {
+    let key = collector.key();
+    let mut senders = self.senders.lock().unwrap();
+    if let Some(sender) = senders
+      .get(&key)
+      .and_then(|(_name, sender)| sender.downcast_ref::<watch::Sender<Option<Result<C::Sample, String>>>>())
+    {
+      return sender.subscribe();
+    }
+    let (sender, samples) = watch::channel(None);
+    senders.insert(key.clone(), (C::NAME, Box::new(sender.clone())));
+    let bus = self.clone();
+    std::thread::spawn(move || bus.run(key, collector, sender));
+    samples
+  }
+
+  fn run<C: Collector>(&self, key: String, mut collector: C, sender: watch::Sender<Option<Result<C::Sample, String>>>) {
+    loop {
+      let sample = try_collect(&mut collector);
+      match &sample {
+        Ok(sample) => self.metrics.set(C::NAME, sample),
+        Err(_err) => self.metrics.remove(C::NAME),
+      }
+      sender.send_replace(Some(sample));
+      std::thread::sleep(collector.interval());
+
+      // Checked under the lock, so no subscriber is handed a sender that's about to stop
+      let mut senders = self.senders.lock().unwrap();
+      if sender.receiver_count() == 0 {
+        senders.remove(&key);
+        // Another collector of the kind may have replaced it, e.g. after a reload
+        if senders.values().all(|(name, _sender)| *name != C::NAME) {
+          self.metrics.remove(C::NAME);
+        }
+        return;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use super::*;
+
+  /// Counts its samples, which are made up by `sample`
+  struct TestCollector {
+    num_collects: Arc<AtomicUsize>,
+    sample: fn() -> Result<MemorySample, Box<dyn Error>>,
+  }
+
+  impl TestCollector {
+    fn new(sample: fn() -> Result<MemorySample, Box<dyn Error>>) -> Self {
+      TestCollector {
+        num_collects: Arc::default(),
+        sample,
+      }
+    }
+  }
+
+  impl Collector for TestCollector {
+    type Sample = MemorySample;
+    const NAME: &'static str = "test";
+
+    fn key(&self) -> String {
+      Self::NAME.to_string()
+    }
+
+    fn interval(&self) -> Duration {
+      Duration::from_secs(3600)
+    }
+
+    fn collect(&mut self) -> Result<MemorySample, Box<dyn Error>> {
+      self.num_collects.fetch_add(1, Ordering::SeqCst);
+      (self.sample)()
+    }
+  }
+
+  #[test]
+  fn try_collect_turns_errors_and_panics_into_messages() {
+    let mut collector = TestCollector::new(|| Err("no sensor".into()));
+    assert_eq!(try_collect(&mut collector).unwrap_err(), "no sensor");
+    let mut collector = TestCollector::new(|| panic!("index out of bounds"));
+    assert_eq!(try_collect(&mut collector).unwrap_err(), "index out of bounds");
+    let mut collector = TestCollector::new(|| Ok(MemorySample::default()));
+    assert!(try_collect(&mut collector).is_ok());
+  }
+
+  #[test]
+  fn collectors_with_the_same_key_are_shared() {
+    let metrics = Metrics::default();
+    let bus = MetricsBus::new(metrics.clone());
+    let first = TestCollector::new(|| Ok(MemorySample::default()));
+    let num_collects = Arc::clone(&first.num_collects);
+    let second = TestCollector {
+      num_collects: Arc::clone(&num_collects),
+      ..TestCollector::new(|| Ok(MemorySample::default()))
+    };
+    let mut first_samples = bus.subscribe(first);
+    let mut second_samples = bus.subscribe(second);
+    futures_lite::future::block_on(async {
+      first_samples.wait_for(Option::is_some).await.unwrap();
+      second_samples.wait_for(Option::is_some).await.unwrap();
+    });
+    assert_eq!(num_collects.load(Ordering::SeqCst), 1);
+    assert!(metrics.to_json().get("test").is_some());
+  }
+
+  #[test]
+  fn reads_temperatures_in_millidegrees() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("temp1_input");
+    std::fs::write(&path, "45500\n").unwrap();
+    assert_eq!(read_temperature(&path).unwrap(), 45.5);
+    std::fs::write(&path, "N/A\n").unwrap();
+    assert!(read_temperature(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+    let err = read_temperature(&path).unwrap_err().to_string();
+    assert!(err.contains(&path.display().to_string()), "{}", err);
+  }
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::io;
+use std::time::{Duration, Instant};
+
+use getifs::Ifv4Net;
+use regex::Regex;
+use serde::Serializer;
+use serde_derive::Serialize;
+use sysinfo::Networks;
+
+use super::Collector;
+use crate::config::NetworkConfig;
+use crate::open_metrics::{MetricType, OpenMetrics, OpenMetricsWriter};
+
+#[derive(Default, Clone, Debug, Serialize)]
+pub struct NetworkSample {
+  pub network_name: String,
+  #[serde(serialize_with = "serialize_ips")]
+  pub local_ips: Vec<Ifv4Net>,
+  /// Bytes, like the rest
+  pub total_received: u64,
+  pub total_transmitted: u64,
+  /// Bytes per second
+  pub upload_speed: f32,
+  pub download_speed: f32,
+}
+
+/// Byte counters only, rates are better left to the queries
+impl OpenMetrics for NetworkSample {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    let labels = [("interface", self.network_name.as_str())];
+    writer
+      .family(MetricType::Counter, "received", Some("bytes"), "Bytes received")
+      .sample(&labels, self.total_received as f64);
+    writer
+      .family(MetricType::Counter, "transmitted", Some("bytes"), "Bytes transmitted")
+      .sample(&labels, self.total_transmitted as f64);
+  }
+}
+
+/// In CIDR notation, e.g. `192.168.1.2/24`
+fn serialize_ips<S: Serializer>(ips: &[Ifv4Net], serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.collect_seq(ips.iter().map(|ip| format!("{}/{}", ip.addr(), ip.prefix_len())))
+}
+
+/// Collect into a vector, so we can test if there's no IP, or to print them
+fn get_local_ips(network_name: &str) -> io::Result<Vec<Ifv4Net>> {
+  let Some(interface) = getifs::interfaces()?
+    .into_iter()
+    .find(|interface| interface.name() == network_name)
+  else {
+    return Ok(vec![]);
+  };
+  Ok(interface.ipv4_addrs()?.to_vec())
+}
+
+/// The first interface matching the regex that has an IP. `None` when disconnected.
+pub struct NetworkCollector {
+  networks: Networks,
+  interface_regex: Regex,
+  update_interval: u64,
+  /// Speeds are measured over the time since
+  last_refresh: Option<Instant>,
+}
+
+impl NetworkCollector {
+  pub fn new(config: &NetworkConfig) -> Self {
+    NetworkCollector {
+      networks: Networks::new(),
+      interface_regex: (*config.interface_regex).clone(),
+      update_interval: config.update_interval,
+      last_refresh: None,
+    }
+  }
+}
+
+impl Collector for NetworkCollector {
+  type Sample = Option<NetworkSample>;
+  const NAME: &'static str = "network";
+
+  fn key(&self) -> String {
+    format!("{}:{}:{}", Self::NAME, self.interface_regex, self.update_interval)
+  }
+
+  fn interval(&self) -> Duration {
+    Duration::from_secs(self.update_interval)
+  }
+
+  fn collect(&mut self) -> Result<Option<NetworkSample>, Box<dyn Error>> {
+    let mut res = NetworkSample::default();
+    self.networks.refresh(true);
+    let now = Instant::now();
+    let elapsed = self
+      .last_refresh
+      .replace(now)
+      .map_or(self.interval(), |last_refresh| now - last_refresh);
+
+    let Some((network_name, network_data)) = self
+      .networks
+      .into_iter()
+      .find(|(interface_name, _data)| self.interface_regex.is_match(interface_name))
+    else {
+      return Ok(None);
+    };
+
+    res.local_ips = get_local_ips(network_name)?;
+    if res.local_ips.is_empty() {
+      return Ok(None);
+    }
+    res.network_name = network_name.to_string();
+
+    res.total_received = network_data.total_received();
+    res.total_transmitted = network_data.total_transmitted();
+    res.download_speed = network_data.received() as f32 / elapsed.as_secs_f32();
+    res.upload_speed = network_data.transmitted() as f32 / elapsed.as_secs_f32();
+
+    Ok(Some(res))
+  }
+}
@@ -0,0 +1,20 @@
+use freya::prelude::*;
+
+use crate::config_file::ConfigError;
+use crate::freya_utils::color_label;
+
+/// Shows config files that failed to load. Panels below it keep running with the last config that loaded.
+pub fn config_error_component(errors: &[ConfigError], color: Color) -> Rect {
+  rect().width(Size::percent(100.)).children(
+    errors
+      .iter()
+      .flat_map(|error| {
+        error
+          .to_string()
+          .lines()
+          .map(|line| color_label(color, line.to_string()))
+          .collect::<Vec<Label>>()
+      })
+      .collect::<Vec<Label>>(),
+  )
+}
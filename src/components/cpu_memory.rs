@@ -1,182 +1,65 @@
-use std::collections::HashSet;
-use std::ops::Range;
 use std::process::Command;
 use std::time::Duration;
 
-use async_io::Timer;
-use binary_heap_plus::BinaryHeap;
-use circular_queue::CircularQueue;
 use freya::prelude::*;
 use freya::text_edit::Clipboard;
-use futures_lite::StreamExt;
 use lazy_static::lazy_static;
 use velcro::vec;
 
 use regex::Regex;
-use sysinfo::{
-  Components, CpuRefreshKind, MemoryRefreshKind, Pid, ProcessRefreshKind, ProcessStatus, ProcessesToUpdate,
-  RefreshKind, System, UpdateKind,
-};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
 
+use crate::collectors::{
+  CpuCollector, CpuSample, CpuTimes, MemoryCollector, MemorySample, MetricsBus, ProcessCollector, ProcessSample,
+  ProcessesSample, for_each_sample,
+};
 use crate::config::CpuMemoryConfig;
-use crate::custom_components::create_graph;
+use crate::cpu_cores::CoreKind;
+use crate::custom_components::{create_graph, create_stacked_graph};
 use crate::format_size::format_size;
 use crate::freya_utils::{
   border_fill_width, cursor_area, flex_cont, label_with_value_factory, right_value_label, value_label_factory,
 };
+use crate::history::{History, HistoryStore};
 use crate::serde_structs::SerdeCommand;
 use crate::styles_config::{CpuMemoryStyles, GlobalStyles};
 use crate::utils::{self, MEMORY_DECIMAL_PLACES, format_used};
 
-#[derive(Default, Clone, Debug)]
-struct CpuData {
-  frequency: f32,
-  temperature: f32,
-  usage: f32,
-  core_usage: Vec<f32>,
-}
-
-#[derive(Default, Clone, Debug)]
-struct MemoryData {
-  memory_usage: u64,
-  swap_usage: u64,
-}
-
-#[derive(Default, Clone)]
-struct ProcessesData {
-  top_cpu: Vec<ProcessProps>,
-  top_memory: Vec<ProcessProps>,
-  num_total: usize,
-  num_running: usize,
-}
-
-#[derive(Clone)]
-struct ProcessProps {
-  cmd: String,
-  pid: Pid,
-  cpu_usage: f32,
-  memory_usage: u64,
-}
-
-fn get_cpu_data(system: &mut System, components: &mut Components) -> CpuData {
-  let mut res = CpuData::default();
-  system.refresh_cpu_specifics(CpuRefreshKind::nothing().with_frequency().with_cpu_usage());
-
-  let cpus = system.cpus();
-  res.frequency = cpus[0].frequency() as f32 / 1000.0;
-
-  components.refresh(true);
-  res.temperature = components
-    .into_iter()
-    .find(|component| component.label().eq("coretemp Package id 0"))
-    .unwrap()
-    .temperature()
-    .unwrap();
-
-  res.usage = system.global_cpu_usage();
-  res.core_usage = cpus.iter().map(|cpu| cpu.cpu_usage()).collect();
-  res
-}
-
-fn get_memory_data(system: &mut System) -> MemoryData {
-  let mut res = MemoryData::default();
-  system.refresh_memory();
-  res.memory_usage = system.used_memory();
-  res.swap_usage = system.used_swap();
-  res
-}
-
-fn get_process_data(system: &mut System, num_top_processes: usize) -> ProcessesData {
-  let mut res = ProcessesData::default();
-  system.refresh_processes_specifics(
-    ProcessesToUpdate::All,
-    true,
-    ProcessRefreshKind::nothing()
-      .with_memory()
-      .with_cpu()
-      .with_cmd(UpdateKind::Always),
-  );
-  let pid_to_process = system.processes();
-  let mut task_pids: HashSet<Pid> = HashSet::new();
-  for process in pid_to_process.values() {
-    if let Some(tasks) = process.tasks() {
-      task_pids.extend(tasks)
-    }
-  }
-  let capacity = num_top_processes + 1;
-  let mut top_cpu_id_usage = BinaryHeap::with_capacity_by(capacity, |p1: &(Pid, f32), p2: &(Pid, f32)| {
-    p2.1.partial_cmp(&p1.1).unwrap()
-  });
-  let mut top_memory_id_usage = BinaryHeap::with_capacity_by(capacity, |p1: &(Pid, u64), p2: &(Pid, u64)| {
-    p2.1.partial_cmp(&p1.1).unwrap()
-  });
-  for (pid, process) in pid_to_process {
-    if task_pids.contains(pid) {
-      continue;
-    }
-    if process.status() == ProcessStatus::Run {
-      res.num_running += 1;
-    }
-    if let Some(live_process) = system.process(*pid) {
-      res.num_total += 1;
-      top_cpu_id_usage.push((*pid, live_process.cpu_usage()));
-      top_memory_id_usage.push((*pid, live_process.memory()));
-      if top_cpu_id_usage.len() > num_top_processes {
-        top_cpu_id_usage.pop();
-        top_memory_id_usage.pop();
-      }
-    }
-  }
-  let pid_to_props = |pid| {
-    let process = pid_to_process.get(pid).unwrap();
-    let args = process
-      .cmd()
-      .iter()
-      .skip(1)
-      .fold(String::new(), |res, cur| res + cur.to_str().unwrap() + " ");
-    ProcessProps {
-      cmd: format!("{} {}", process.name().to_str().unwrap(), args),
-      pid: *pid,
-      cpu_usage: process.cpu_usage(),
-      memory_usage: process.memory(),
-    }
-  };
-  let top_cpu_vec = top_cpu_id_usage.into_sorted_vec();
-  res.top_cpu = top_cpu_vec.iter().map(|(pid, _)| pid_to_props(pid)).collect();
-  let top_memory_vec = top_memory_id_usage.into_sorted_vec();
-  res.top_memory = top_memory_vec.iter().map(|(pid, _)| pid_to_props(pid)).collect();
-  res
-}
+use super::on_sample;
 
 const CPU_MODEL_REMOVE: &[&str] = &["(R)", "(TM)", "!"];
+/// Written by `show-ram-frequency.service`, as reading it needs root
+const LSHW_MEMORY_PATH: &str = "/run/lshw-memory.txt";
 
-fn cpu_bars_component(performant_range: Range<usize>, cpu_core_usage: &[f32]) -> Rect {
+fn cpu_bars_component(cpu: &CpuSample) -> Rect {
   let styles = use_consume::<CpuMemoryStyles>();
   let global_styles = use_consume::<GlobalStyles>();
   let flex_cont = flex_cont(styles.bar_h_gap);
   let bar_width =
     (global_styles.container_width - (styles.bars_per_row - 1) as f32 * styles.bar_h_gap) / styles.bars_per_row as f32;
   rect().spacing(styles.bars_v_gap).children(
-    (0..cpu_core_usage.len().div_ceil(styles.bars_per_row))
+    (0..cpu.core_usage.len().div_ceil(styles.bars_per_row))
       .map(|i| {
         flex_cont.children(
-          (0..(cpu_core_usage.len() - i * styles.bars_per_row).min(styles.bars_per_row)).map(|j| {
+          (0..(cpu.core_usage.len() - i * styles.bars_per_row).min(styles.bars_per_row)).map(|j| {
+            let core = i * styles.bars_per_row + j;
+            let is_efficient = cpu.core_kinds.get(core) == Some(&CoreKind::Efficient);
             rect()
               .width(Size::px(bar_width))
               .height(Size::px(styles.bar_height))
-              .border(Some(if performant_range.contains(&(i * styles.bars_per_row + j)) {
-                border_fill_width(*styles.bar_border_color, styles.bar_border_width)
-              } else {
+              .border(Some(if is_efficient {
                 border_fill_width(*styles.bar_efficient_border_color, styles.bar_efficient_border_width)
+              } else {
+                border_fill_width(*styles.bar_border_color, styles.bar_border_width)
               }))
               .child::<Rect>(
                 rect()
-                  .width(Size::percent(cpu_core_usage[i * styles.bars_per_row + j]))
+                  .width(Size::percent(cpu.core_usage[core]))
                   .height(Size::percent(100.))
-                  .background(if performant_range.contains(&(i * styles.bars_per_row + j)) {
-                    *styles.bar_fill_color
-                  } else {
+                  .background(if is_efficient {
                     *styles.bar_efficient_fill_color
+                  } else {
+                    *styles.bar_fill_color
                   }),
               )
           }),
@@ -186,7 +69,18 @@ fn cpu_bars_component(performant_range: Range<usize>, cpu_core_usage: &[f32]) ->
   )
 }
 
-fn cpu_graphs_component(cpu_hist: CircularQueue<f32>, memory_swap_hist: [CircularQueue<f32>; 2]) -> Rect {
+/// The top-like abbreviations, colored like the graph
+fn cpu_times_component(times: &CpuTimes, colors: [Color; 5], h_gap: f32) -> Rect {
+  flex_cont(h_gap).children(
+    ["us", "sy", "wa", "hi", "st"]
+      .iter()
+      .zip(times.shares())
+      .zip(colors)
+      .map(|((name, share), color)| label().color(color).text(format!("{:.1} {}", share, name))),
+  )
+}
+
+fn cpu_graphs_component(cpu_times_hist: [History; 5], memory_swap_hist: [History; 2]) -> Rect {
   let styles = use_consume::<CpuMemoryStyles>();
   let flex_cont = flex_cont(styles.graph_h_gap);
   flex_cont.children([
@@ -197,7 +91,7 @@ fn cpu_graphs_component(cpu_hist: CircularQueue<f32>, memory_swap_hist: [Circula
         *styles.graph_cpu_border_color,
         styles.graph_cpu_border_width,
       ))
-      .child(create_graph([cpu_hist], [*styles.graph_cpu_fill_color])),
+      .child(create_stacked_graph(cpu_times_hist, cpu_times_colors(&styles))),
     rect()
       .width(Size::flex(1.))
       .height(Size::px(styles.graph_height))
@@ -212,6 +106,16 @@ fn cpu_graphs_component(cpu_hist: CircularQueue<f32>, memory_swap_hist: [Circula
   ])
 }
 
+fn cpu_times_colors(styles: &CpuMemoryStyles) -> [Color; 5] {
+  [
+    *styles.graph_cpu_fill_color,
+    *styles.graph_cpu_system_fill_color,
+    *styles.graph_cpu_iowait_fill_color,
+    *styles.graph_cpu_irq_fill_color,
+    *styles.graph_cpu_steal_fill_color,
+  ]
+}
+
 fn process_table_row(
   cmd: &str,
   pid: &str,
@@ -237,16 +141,16 @@ fn process_table_row(
     ])
 }
 
-fn process_table_component(processes: ProcessesData, num_cpus: usize, top_command: SerdeCommand) -> CursorArea {
+fn process_table_component(processes: ProcessesSample, num_cpus: usize, top_command: SerdeCommand) -> CursorArea {
   let styles = use_consume::<CpuMemoryStyles>();
 
-  let format_cpu = |process: &ProcessProps| format!("{:.2}", process.cpu_usage / num_cpus as f32);
-  let format_memory = |process: &ProcessProps| format_size(process.memory_usage, MEMORY_DECIMAL_PLACES);
+  let format_cpu = |process: &ProcessSample| format!("{:.2}", process.cpu_usage / num_cpus as f32);
+  let format_memory = |process: &ProcessSample| format_size(process.memory_usage, MEMORY_DECIMAL_PLACES);
   let header_color = styles.ps_header_color;
   let sort_cpu_color = styles.ps_sort_cpu_color;
   let sort_memory_color = styles.ps_sort_memory_color;
   let widths = [styles.ps_pid_width, styles.ps_cpu_width, styles.ps_memory_width];
-  let create_data_row = |p: &ProcessProps, is_cpu: bool| {
+  let create_data_row = |p: &ProcessSample, is_cpu: bool| {
     process_table_row(
       &p.cmd,
       &p.pid.to_string(),
@@ -286,142 +190,211 @@ fn process_table_component(processes: ProcessesData, num_cpus: usize, top_comman
   )
 }
 
-pub fn cpu_memory_component() -> Rect {
-  let config = use_consume::<CpuMemoryConfig>();
-  let styles = use_consume::<CpuMemoryStyles>();
-  let global_styles = use_consume::<GlobalStyles>();
+#[derive(Clone)]
+struct CpuMemoryInfo {
+  cpu_model: String,
+  num_cpus: usize,
+  memory_frequency: String,
+  memory_total: u64,
+  swap_total: u64,
+}
 
+/// `None` when it's not known. The rest of the panel works without it.
+fn read_memory_frequency() -> Option<String> {
+  lazy_static! {
+    static ref RE_FREQUENCY: Regex = Regex::new(r"\d+ MHz").unwrap();
+  }
+  let lshw_output = std::fs::read_to_string(LSHW_MEMORY_PATH)
+    .inspect_err(|err| {
+      eprintln!(
+        "Failed to read the memory frequency from {}: {}. Is show-ram-frequency.service enabled?",
+        LSHW_MEMORY_PATH, err
+      )
+    })
+    .ok()?;
+  let memory_frequency = RE_FREQUENCY
+    .find(&lshw_output)
+    .map(|frequency| frequency.as_str().to_string());
+  if memory_frequency.is_none() {
+    eprintln!("No memory frequency in {}", LSHW_MEMORY_PATH);
+  }
+  memory_frequency
+}
+
+fn get_cpu_memory_info() -> Result<CpuMemoryInfo, String> {
   let refresh_kind = RefreshKind::nothing()
     .with_cpu(CpuRefreshKind::nothing())
     .with_memory(MemoryRefreshKind::nothing().with_ram().with_swap());
-  let mut system = System::new_with_specifics(refresh_kind);
-  let mut components = Components::new();
+  let system = System::new_with_specifics(refresh_kind);
 
   let cpus = system.cpus();
-  let mut cpu_model = cpus[0].brand().to_string();
+  let mut cpu_model = cpus.first().ok_or("No CPU found")?.brand().to_string();
   for &s in CPU_MODEL_REMOVE {
     cpu_model = cpu_model.replace(s, "");
   }
   let num_cpus = cpus.len();
 
-  lazy_static! {
-    static ref RE_CPU_RANGE: Regex = Regex::new(r"(\d+)-(\d+)").unwrap();
-  }
-  let mut cpu_performant_range: Range<usize> = 0..num_cpus;
-  if let Ok(contents) = std::fs::read_to_string("/sys/devices/cpu_core/cpus") {
-    let groups = RE_CPU_RANGE.captures(&contents).unwrap();
-    cpu_performant_range = groups[1].parse().unwrap()..groups[2].parse::<usize>().unwrap() + 1;
-  }
-
-  lazy_static! {
-    static ref RE_FREQUENCY: Regex = Regex::new(r"\d+ MHz").unwrap();
-  }
-  let lshw_output = std::fs::read_to_string("/run/lshw-memory.txt").unwrap();
-  let memory_frequency = RE_FREQUENCY.find(&lshw_output).unwrap().as_str();
-  let memory_total = system.total_memory();
-  let swap_total = system.total_swap();
-
-  let mut cpu_data = use_state(CpuData::default);
-  let mut memory_data = use_state(MemoryData::default);
-  let mut processes_data = use_state(ProcessesData::default);
+  Ok(CpuMemoryInfo {
+    cpu_model,
+    num_cpus,
+    memory_frequency: read_memory_frequency().unwrap_or_else(|| "N/A".to_string()),
+    memory_total: system.total_memory(),
+    swap_total: system.total_swap(),
+  })
+}
 
+pub fn cpu_memory_component() -> Result<Rect, String> {
+  let config = use_consume::<CpuMemoryConfig>();
+  let styles = use_consume::<CpuMemoryStyles>();
+  let global_styles = use_consume::<GlobalStyles>();
+  let bus = use_consume::<MetricsBus>();
+  let history = use_consume::<HistoryStore>();
+
+  let CpuMemoryInfo {
+    cpu_model,
+    num_cpus,
+    memory_frequency,
+    memory_total,
+    swap_total,
+  } = use_hook(get_cpu_memory_info)?;
+
+  let mut cpu_data = use_state(CpuSample::default);
+  let mut memory_data = use_state(MemorySample::default);
+  let mut processes_data = use_state(ProcessesSample::default);
+  let cpu_error = use_state(|| None::<String>);
+  let memory_error = use_state(|| None::<String>);
+  let processes_error = use_state(|| None::<String>);
+
+  let interval = Duration::from_secs(config.update_interval);
   let hist_size = ((global_styles.container_width - styles.graph_h_gap) / 2.) as usize;
-  let mut cpu_hist = use_state(|| CircularQueue::with_capacity(hist_size));
-  let mut memory_hist = use_state(|| CircularQueue::with_capacity(hist_size));
-  let mut swap_hist = use_state(|| CircularQueue::with_capacity(hist_size));
-
-  let mut uptime = use_state(|| 0u64);
+  let cpu_times_names = CpuTimes::NAMES.map(|name| format!("cpu_{}", name));
+  let mut cpu_times_hist = use_state(|| {
+    cpu_times_names
+      .clone()
+      .map(|name| history.get(&name, hist_size, interval))
+  });
+  let mut memory_hist = use_state(|| history.get("memory", hist_size, interval));
+  let mut swap_hist = use_state(|| history.get("swap", hist_size, interval));
 
   use_hook(|| {
-    spawn(async move {
-      loop {
-        cpu_data.set(get_cpu_data(&mut system, &mut components));
-        memory_data.set(get_memory_data(&mut system));
-
-        cpu_hist.write().push(cpu_data.read().usage / 100.0);
-        let memory_ratio = memory_data.read().memory_usage as f32 / memory_total as f32;
-        memory_hist.write().push(memory_ratio);
-        let swap_ratio = memory_data.read().swap_usage as f32 / swap_total as f32;
-        swap_hist.write().push(swap_ratio);
-
-        uptime.set(System::uptime());
-        processes_data.set(get_process_data(&mut system, config.process_list.num_processes));
-
-        Timer::interval(Duration::from_secs(config.update_interval))
-          .next()
-          .await;
-      }
-    })
+    let cpu_samples = bus.subscribe(CpuCollector::new(&config));
+    let memory_samples = bus.subscribe(MemoryCollector::new(interval));
+    let processes_samples = bus.subscribe(ProcessCollector::new(config.process_list.num_processes, interval));
+    let cpu_history = history.clone();
+    spawn(for_each_sample(
+      cpu_samples,
+      on_sample(cpu_error, move |sample: CpuSample| {
+        for (hist, share) in cpu_times_hist.write().iter_mut().zip(sample.times.shares()) {
+          hist.push(share / 100.0);
+        }
+        for (name, hist) in cpu_times_names.iter().zip(cpu_times_hist.read().iter()) {
+          cpu_history.set(name, hist);
+        }
+        cpu_data.set(sample);
+      }),
+    ));
+    let memory_history = history.clone();
+    spawn(for_each_sample(
+      memory_samples,
+      on_sample(memory_error, move |sample: MemorySample| {
+        memory_hist
+          .write()
+          .push(sample.memory_usage as f32 / sample.memory_total as f32);
+        swap_hist
+          .write()
+          .push(sample.swap_usage as f32 / sample.swap_total as f32);
+        memory_history.set("memory", &memory_hist.read());
+        memory_history.set("swap", &swap_hist.read());
+        memory_data.set(sample);
+      }),
+    ));
+    spawn(for_each_sample(
+      processes_samples,
+      on_sample(processes_error, move |sample| processes_data.set(sample)),
+    ));
   });
+  if let Some(error) = [cpu_error, memory_error, processes_error]
+    .iter()
+    .find_map(|error| error.read().clone())
+  {
+    return Err(error);
+  }
 
   let value_color = styles.value_color;
   let flex_cont = flex_cont(global_styles.h_gap);
   let value_label = value_label_factory(*value_color);
   let label_with_value = label_with_value_factory(None::<Color>, *value_color);
-
-  rect().children([
-    flex_cont
-      .children([
-        "CPU".into_element(),
-        cursor_area(CursorIcon::Copy)
-          .child(value_label(cpu_model.clone()).on_mouse_down(move |_| Clipboard::set(cpu_model.clone()).unwrap()))
+  let temperature = cpu_data
+    .read()
+    .temperature
+    .map_or_else(|| "N/A".to_string(), |temperature| format!("{}°C", temperature));
+
+  Ok(rect().children(vec![
+      flex_cont
+        .children([
+          "CPU".into_element(),
+          cursor_area(CursorIcon::Copy)
+            .child(value_label(cpu_model.clone()).on_mouse_down(move |_| Clipboard::set(cpu_model.clone()).unwrap()))
+            .into_element(),
+          right_value_label(*value_color, temperature).into_element(),
+        ])
+        .into_element(),
+      flex_cont
+        .children([
+          label_with_value("Frequency", format!("{:.2} GHz", cpu_data.read().frequency)),
+          label_with_value("Usage", format!("{:.1}%", cpu_data.read().usage)),
+        ])
+        .into_element(),
+      flex_cont
+        .children([
+          label_with_value("Uptime", utils::format_duration(cpu_data.read().uptime)).into_element(),
+          label_with_value(
+            "Processes",
+            format!(
+              "{} / {: >4}",
+              processes_data.read().num_running,
+              processes_data.read().num_total
+            ),
+          )
           .into_element(),
-        right_value_label(*value_color, format!("{}°C", cpu_data.read().temperature)).into_element(),
-      ])
-      .into_element(),
-    flex_cont
-      .children([
-        label_with_value("Frequency", format!("{:.2} GHz", cpu_data.read().frequency)),
-        label_with_value("Usage", format!("{:.1}%", cpu_data.read().usage)),
-      ])
-      .into_element(),
-    flex_cont
-      .children([
-        label_with_value("Uptime", utils::format_duration(uptime())).into_element(),
-        label_with_value(
-          "Processes",
-          format!(
-            "{} / {: >4}",
-            processes_data.read().num_running,
-            processes_data.read().num_total
-          ),
-        )
+        ])
         .into_element(),
-      ])
-      .into_element(),
-    cpu_bars_component(cpu_performant_range, &cpu_data.read().core_usage).into_element(),
-    rect()
-      .width(Size::percent(100.))
-      .direction(Direction::Horizontal)
-      .main_align(Alignment::SpaceBetween)
-      .children([
-        "Memory".into_element(),
-        value_label(format!("{: >8}", memory_frequency)).into_element(),
-        value_label(format!(
-          "{: >28}",
-          format_used(memory_data.read().memory_usage, memory_total)
-        ))
+      ..config.show_cpu_times.then(|| {
+        cpu_times_component(&cpu_data.read().times, cpu_times_colors(&styles), global_styles.h_gap).into_element()
+      }),
+      cpu_bars_component(&cpu_data.read()).into_element(),
+      rect()
+        .width(Size::percent(100.))
+        .direction(Direction::Horizontal)
+        .main_align(Alignment::SpaceBetween)
+        .children([
+          "Memory".into_element(),
+          value_label(format!("{: >8}", memory_frequency)).into_element(),
+          value_label(format!(
+            "{: >28}",
+            format_used(memory_data.read().memory_usage, memory_total)
+          ))
+          .into_element(),
+        ])
+        .into_element(),
+      rect()
+        .width(Size::percent(100.))
+        .direction(Direction::Horizontal)
+        .children([
+          "Swap".into_element(),
+          right_value_label(*value_color, format_used(memory_data.read().swap_usage, swap_total)).into_element(),
+        ])
         .into_element(),
-      ])
+      cpu_graphs_component(
+        (*cpu_times_hist.read()).clone(),
+        [(*memory_hist.read()).clone(), (*swap_hist.read()).clone()],
+      )
       .into_element(),
-    rect()
-      .width(Size::percent(100.))
-      .direction(Direction::Horizontal)
-      .children([
-        "Swap".into_element(),
-        right_value_label(*value_color, format_used(memory_data.read().swap_usage, swap_total)).into_element(),
-      ])
+      process_table_component(
+        (*processes_data.read()).clone(),
+        num_cpus,
+        config.process_list.top_command,
+      )
       .into_element(),
-    cpu_graphs_component(
-      (*cpu_hist.read()).clone(),
-      [(*memory_hist.read()).clone(), (*swap_hist.read()).clone()],
-    )
-    .into_element(),
-    process_table_component(
-      (*processes_data.read()).clone(),
-      num_cpus,
-      config.process_list.top_command,
-    )
-    .into_element(),
-  ])
+    ]))
 }
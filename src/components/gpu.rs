@@ -1,14 +1,14 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use async_io::Timer;
 use freya::prelude::*;
 
 use freya::text_edit::Clipboard;
-use futures_lite::stream::StreamExt;
-use nvml_wrapper::enum_wrappers::device::{Clock, ClockId, TemperatureSensor, TemperatureThreshold};
-use nvml_wrapper::{Device, Nvml};
+use nvml_wrapper::Nvml;
+use nvml_wrapper::enum_wrappers::device::TemperatureThreshold;
+use nvml_wrapper::error::NvmlError;
 
+use crate::collectors::{GpuCollector, GpuSample, MetricsBus, for_each_sample, get_gpu};
 use crate::config::GpuConfig;
 use crate::freya_utils::{
   color_label, cursor_area, flex_cont, horizontal_cont, label_with_value_factory, right_value_label,
@@ -17,74 +17,57 @@ use crate::freya_utils::{
 use crate::styles_config::{GlobalStyles, GpuStyles};
 use crate::utils::format_used;
 
-#[derive(Default, Clone, Debug)]
-struct GpuData {
-  temperature: u32,
-  utilization_rates: u32,
-  gpu_frequency: u32,
-  memory_frequency: u32,
-  memory_used: u64,
-}
-
-fn get_gpu(nvml: &Nvml) -> Device<'_> {
-  nvml.device_by_index(0).unwrap()
-}
+use super::on_sample;
 
-fn get_gpu_data(nvml: &Nvml) -> GpuData {
-  let mut res = GpuData::default();
-  let gpu = get_gpu(nvml);
-
-  res.temperature = gpu.temperature(TemperatureSensor::Gpu).unwrap();
-  res.utilization_rates = gpu.utilization_rates().unwrap().gpu;
-  res.gpu_frequency = gpu.clock(Clock::Graphics, ClockId::Current).unwrap();
-  res.memory_frequency = gpu.clock(Clock::Memory, ClockId::Current).unwrap();
-
-  let memory_info = gpu.memory_info().unwrap();
-  res.memory_used = memory_info.used;
-  res
+#[derive(Clone)]
+struct GpuInfo {
+  model: String,
+  temperature_threshold: u32,
+  memory_total: u64,
 }
 
-pub struct GpuComponent {
-  pub nvml: Arc<Nvml>,
+fn get_gpu_info(nvml: &Nvml) -> Result<GpuInfo, NvmlError> {
+  let gpu = get_gpu(nvml)?;
+  Ok(GpuInfo {
+    model: gpu.name()?,
+    temperature_threshold: gpu.temperature_threshold(TemperatureThreshold::Shutdown)?,
+    memory_total: gpu.memory_info()?.total,
+  })
 }
 
-impl PartialEq for GpuComponent {
-  fn eq(&self, _other: &Self) -> bool {
-    true
+pub fn gpu_component() -> Result<Rect, String> {
+  let config = use_consume::<GpuConfig>();
+  let styles = use_consume::<GpuStyles>();
+  let global_styles = use_consume::<GlobalStyles>();
+  let bus = use_consume::<MetricsBus>();
+  let nvml = use_consume::<Arc<Nvml>>();
+
+  let GpuInfo {
+    model,
+    temperature_threshold,
+    memory_total,
+  } = use_hook(|| get_gpu_info(&nvml).map_err(|err| err.to_string()))?;
+
+  let mut data = use_state(GpuSample::default);
+  let error = use_state(|| None::<String>);
+
+  use_hook(|| {
+    let collector = GpuCollector::new(nvml, Duration::from_secs(config.update_interval));
+    spawn(for_each_sample(
+      bus.subscribe(collector),
+      on_sample(error, move |sample| data.set(sample)),
+    ))
+  });
+  if let Some(error) = error.read().clone() {
+    return Err(error);
   }
-}
 
-impl Component for GpuComponent {
-  fn render(&self) -> impl IntoElement {
-    let config = use_consume::<GpuConfig>();
-    let styles = use_consume::<GpuStyles>();
-    let global_styles = use_consume::<GlobalStyles>();
-
-    let gpu = get_gpu(&self.nvml);
-    let model = gpu.name().unwrap();
-    let temperature_threshold = gpu.temperature_threshold(TemperatureThreshold::Shutdown).unwrap();
-    let memory_info = gpu.memory_info().unwrap();
-    let memory_total = memory_info.total;
-
-    let mut data = use_state(GpuData::default);
-
-    let nvml = Arc::clone(&self.nvml);
-    use_hook(|| {
-      spawn(async move {
-        loop {
-          data.set(get_gpu_data(&nvml));
-          Timer::interval(Duration::from_secs(config.update_interval))
-            .next()
-            .await;
-        }
-      })
-    });
-
-    let flex_cont = flex_cont(global_styles.h_gap);
-    let horizontal_cont = horizontal_cont(global_styles.h_gap);
-    let label_with_value = label_with_value_factory(Some(*styles.usage_name_color), *styles.value_color);
-    let value_label = value_label_factory(*styles.value_color);
+  let flex_cont = flex_cont(global_styles.h_gap);
+  let horizontal_cont = horizontal_cont(global_styles.h_gap);
+  let label_with_value = label_with_value_factory(Some(*styles.usage_name_color), *styles.value_color);
+  let value_label = value_label_factory(*styles.value_color);
 
+  Ok(
     rect().children([
       horizontal_cont.children([
         "GPU".into_element(),
@@ -111,6 +94,6 @@ impl Component for GpuComponent {
           value_label(format_used(data.read().memory_used, memory_total)),
         ])
         .main_align(Alignment::SpaceBetween),
-    ])
-  }
+    ]),
+  )
 }
@@ -1,13 +1,55 @@
+mod config_error;
 mod cpu_memory;
 mod disk;
 mod gpu;
 mod machine_info;
 mod network;
+mod panel_error;
 mod weather;
 
-pub use cpu_memory::cpu_memory_component;
-pub use disk::disk_component;
-pub use gpu::GpuComponent;
-pub use machine_info::machine_info_component;
-pub use network::network_component;
-pub use weather::weather_component;
+use freya::prelude::*;
+
+use crate::config::LayoutItem;
+use crate::custom_components::create_separator;
+use crate::styles_config::GlobalStyles;
+pub use config_error::config_error_component;
+use cpu_memory::cpu_memory_component;
+use disk::disk_component;
+use gpu::gpu_component;
+use machine_info::machine_info_component;
+use network::network_component;
+use panel_error::PanelError;
+use weather::weather_component;
+
+/// Passes each sample to `f`. A failed one goes to `error` instead, until a sample succeeds again.
+fn on_sample<T>(mut error: State<Option<String>>, mut f: impl FnMut(T)) -> impl FnMut(Result<T, String>) {
+  move |sample| match sample {
+    Ok(sample) => {
+      if error.read().is_some() {
+        error.set(None);
+      }
+      f(sample);
+    }
+    Err(err) => error.set(Some(err)),
+  }
+}
+
+/// Renders a layout item in its own scope, so that a panel's hooks don't depend on the panels around it. A panel that
+/// fails shows its error in place, and the rest keep running.
+#[derive(PartialEq)]
+pub struct Panel(pub LayoutItem);
+
+impl Component for Panel {
+  fn render(&self) -> impl IntoElement {
+    let panel = match self.0 {
+      LayoutItem::Weather => weather_component().map(IntoElement::into_element),
+      LayoutItem::MachineInfo => machine_info_component().map(IntoElement::into_element),
+      LayoutItem::CpuMemory => cpu_memory_component().map(IntoElement::into_element),
+      LayoutItem::Disk => disk_component().map(IntoElement::into_element),
+      LayoutItem::Gpu => gpu_component().map(IntoElement::into_element),
+      LayoutItem::Network => network_component(),
+      LayoutItem::Separator => Ok(create_separator(use_consume::<GlobalStyles>().separator_height).into_element()),
+    };
+    panel.unwrap_or_else(|message| PanelError { item: self.0, message }.into_element())
+  }
+}
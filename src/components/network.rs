@@ -1,70 +1,25 @@
 use std::time::Duration;
 
 use async_io::Timer;
-use circular_queue::CircularQueue;
 use freya::prelude::*;
 use freya::text_edit::Clipboard;
 use futures_lite::stream::StreamExt;
-use getifs::Ifv4Net;
 use join_string::Join;
 use public_ip::dns::GOOGLE_V6;
-use regex::Regex;
-use sysinfo::Networks;
 
+use crate::collectors::{MetricsBus, NetworkCollector, NetworkSample, for_each_sample};
 use crate::config::NetworkConfig;
 use crate::custom_components::create_graph;
 use crate::format_size::{format_size, format_speed};
 use crate::freya_utils::{
   border_fill_width, color_label, cursor_area, flex_cont, label_with_value_factory, right_value_label,
 };
+use crate::history::{History, HistoryStore};
 use crate::styles_config::{GlobalStyles, NetworkStyles};
 
-#[derive(Default, Clone, Debug)]
-struct NetworkData {
-  network_name: String,
-  local_ips: Vec<Ifv4Net>,
-  total_received: u64,
-  total_transmitted: u64,
-  upload_speed: f32,
-  download_speed: f32,
-}
-
-/// Collect into a vector, so we can test if there's no IP, or to print them
-fn get_local_ips(network_name: &str) -> Vec<Ifv4Net> {
-  getifs::interfaces()
-    .unwrap()
-    .into_iter()
-    .find_map(|interface| (interface.name() == network_name).then_some(interface.ipv4_addrs().unwrap().to_vec()))
-    .unwrap_or_default()
-}
-
-fn get_network_data(networks: &mut Networks, interface_regex: Regex, update_interval: u64) -> Option<NetworkData> {
-  let mut res = NetworkData::default();
-  networks.refresh(true);
-
-  let (network_name, network_data) = networks
-    .into_iter()
-    .find(|(interface_name, _data)| interface_regex.is_match(interface_name))?;
+use super::on_sample;
 
-  res.local_ips = get_local_ips(network_name);
-  if res.local_ips.is_empty() {
-    return None;
-  }
-  res.network_name = network_name.to_string();
-
-  res.total_received = network_data.total_received();
-  res.total_transmitted = network_data.total_transmitted();
-  res.download_speed = network_data.received() as f32 / update_interval as f32;
-  res.upload_speed = network_data.transmitted() as f32 / update_interval as f32;
-
-  Some(res)
-}
-
-fn network_graphs_component(
-  download_hist: CircularQueue<f32>,
-  upload_hist: CircularQueue<f32>,
-  styles: NetworkStyles,
-) -> Element {
+fn network_graphs_component(download_hist: History, upload_hist: History, styles: NetworkStyles) -> Element {
   let flex_cont = flex_cont(styles.graph_h_gap);
   flex_cont
     .children([
@@ -90,51 +45,54 @@ fn network_graphs_component(
 
 const NETWORK_DECIMAL_PLACES: usize = 2usize;
 
-pub fn network_component() -> Element {
+pub fn network_component() -> Result<Element, String> {
   let config = use_consume::<NetworkConfig>();
   let styles = use_consume::<NetworkStyles>();
   let global_styles = use_consume::<GlobalStyles>();
+  let bus = use_consume::<MetricsBus>();
+  let history = use_consume::<HistoryStore>();
 
-  let mut networks = Networks::new();
-
-  let mut data = use_state(NetworkData::default);
+  let mut data = use_state(NetworkSample::default);
 
   let hist_size = ((global_styles.container_width - styles.graph_h_gap) / 2.) as usize;
-  let mut download_hist = use_state(|| CircularQueue::with_capacity(hist_size));
-  let mut upload_hist = use_state(|| CircularQueue::with_capacity(hist_size));
+  let interval = Duration::from_secs(config.update_interval);
+  let mut download_hist = use_state(|| history.get("download", hist_size, interval));
+  let mut upload_hist = use_state(|| history.get("upload", hist_size, interval));
 
   let mut public_ip_str = use_state(|| "".to_string());
   let mut local_ips_str = use_state(|| "".to_string());
+  let error = use_state(|| None::<String>);
 
   use_hook(|| {
-    spawn(async move {
-      loop {
-        if let Some(cur_data) =
-          get_network_data(&mut networks, (*config.interface_regex).clone(), config.update_interval)
-        {
-          let graph_config = &config.graphs;
-          let download_ratio = cur_data.download_speed / graph_config.download.maximum_bytes_per_sec as f32;
-          download_hist.write().push(download_ratio);
-          let upload_ratio = cur_data.upload_speed / graph_config.upload.maximum_bytes_per_sec as f32;
-          upload_hist.write().push(upload_ratio);
-
-          // Only include IPv4, as IPv6 addresses are too long
-          local_ips_str.set(
-            cur_data
-              .local_ips
-              .iter()
-              .map(|ip| ip.addr().to_string())
-              .join(" ")
-              .to_string(),
-          );
-
-          data.set(cur_data);
-        }
-        Timer::interval(Duration::from_secs(config.update_interval))
-          .next()
-          .await;
-      }
-    });
+    let samples = bus.subscribe(NetworkCollector::new(&config));
+    let graph_config = config.graphs.clone();
+    // Keeps the last sample while disconnected
+    spawn(for_each_sample(
+      samples,
+      on_sample(error, move |sample: Option<NetworkSample>| {
+        let Some(cur_data) = sample else {
+          return;
+        };
+        let download_ratio = cur_data.download_speed / graph_config.download.maximum_bytes_per_sec as f32;
+        download_hist.write().push(download_ratio);
+        let upload_ratio = cur_data.upload_speed / graph_config.upload.maximum_bytes_per_sec as f32;
+        upload_hist.write().push(upload_ratio);
+        history.set("download", &download_hist.read());
+        history.set("upload", &upload_hist.read());
+
+        // Only include IPv4, as IPv6 addresses are too long
+        local_ips_str.set(
+          cur_data
+            .local_ips
+            .iter()
+            .map(|ip| ip.addr().to_string())
+            .join(" ")
+            .to_string(),
+        );
+
+        data.set(cur_data);
+      }),
+    ));
     if let Some(interval) = config.public_ip_retry_timeout {
       spawn(async move {
         loop {
@@ -147,10 +105,14 @@ pub fn network_component() -> Element {
     }
   });
 
+  if let Some(error) = error.read().clone() {
+    return Err(error);
+  }
+
   let flex_cont = flex_cont(global_styles.h_gap);
   let label_with_value = label_with_value_factory(Some(*styles.name_color), *styles.value_color);
 
-  if data.read().local_ips.is_empty() {
+  Ok(if data.read().local_ips.is_empty() {
     color_label(*styles.name_color, "Disconnected").into()
   } else {
     let data_ = data.read();
@@ -197,5 +159,5 @@ pub fn network_component() -> Element {
         network_graphs_component((*download_hist.read()).clone(), (*upload_hist.read()).clone(), styles),
       ])
       .into()
-  }
+  })
 }
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use freya::prelude::*;
+
+use crate::config::LayoutItem;
+use crate::freya_utils::{color_label, cursor_area, flex_cont};
+use crate::styles_config::GlobalStyles;
+
+/// Stands in for a panel that failed to start or to sample. Retrying restarts that panel only.
+#[derive(PartialEq)]
+pub struct PanelError {
+  pub item: LayoutItem,
+  pub message: String,
+}
+
+impl Component for PanelError {
+  fn render(&self) -> impl IntoElement {
+    let global_styles = use_consume::<GlobalStyles>();
+    // Bumped by `dogky msg refresh` too
+    let mut refreshes = use_consume::<State<HashMap<LayoutItem, u64>>>();
+
+    let item = self.item;
+    let name = item.to_possible_value().unwrap().get_name().to_string();
+
+    flex_cont(global_styles.h_gap).children([
+      color_label(*global_styles.error_color, format!("{}: {}", name, self.message))
+        .width(Size::flex(1.))
+        .into_element(),
+      cursor_area(CursorIcon::Pointer)
+        .child(label().text("Retry").on_pointer_press(move |_| {
+          *refreshes.write().entry(item).or_default() += 1;
+        }))
+        .into_element(),
+    ])
+  }
+}
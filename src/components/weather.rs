@@ -1,18 +1,20 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::{Duration, SystemTime};
 
 use async_io::Timer;
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Offset, Utc};
 use freya::prelude::*;
 use futures_lite::stream::StreamExt;
 use heck::ToTitleCase;
 
 use phf::phf_map;
+use reqwest::StatusCode;
 
-use crate::api::{WeatherData, get_weather};
+use crate::api::{ApiError, WeatherData, get_weather};
 use crate::config::WeatherConfig;
 use crate::freya_utils::{center_cont, color_label, cursor_area, emoji_label, value_label_factory};
+use crate::metrics::Metrics;
 use crate::path::get_xdg_dirs;
 use crate::styles_config::{GlobalStyles, WeatherStyles};
 
@@ -30,65 +32,122 @@ static ICON_MAP: phf::Map<&'static str, &'static str> = phf_map! {
 };
 
 fn format_sun_timestamp(timestamp: u64, timezone: FixedOffset) -> String {
-  let date_time = DateTime::from_timestamp(timestamp as i64, 0).unwrap() + timezone;
-  date_time.format("%-l:%M %p").to_string()
+  match DateTime::from_timestamp(timestamp as i64, 0) {
+    Some(date_time) => (date_time + timezone).format("%-l:%M %p").to_string(),
+    None => "?".to_string(),
+  }
 }
 
-fn load_cache(path: PathBuf) -> WeatherData {
-  let data_file = File::open(&path).unwrap();
-  serde_json::from_reader(data_file).unwrap()
+/// The cached data, if it's younger than `max_age`
+fn load_cache(path: &Path, max_age: Duration) -> Result<Option<WeatherData>, Box<dyn std::error::Error>> {
+  let cache_time = match std::fs::metadata(path) {
+    Ok(metadata) => metadata.modified()?,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  // A cache from the future is stale too, e.g. after the clock was set back
+  let is_fresh = SystemTime::now()
+    .duration_since(cache_time)
+    .is_ok_and(|age| age < max_age);
+  if !is_fresh {
+    return Ok(None);
+  }
+  Ok(Some(serde_json::from_reader(File::open(path)?)?))
 }
 
-async fn update_data(config: &WeatherConfig, cache_path: &PathBuf) -> Result<WeatherData, String> {
-  // No need to fetch data from server if cache time is close enough
-  if let Ok(metadata) = std::fs::metadata(cache_path) {
-    let cache_time = metadata.modified().unwrap();
-    let time_since_cache = SystemTime::now().duration_since(cache_time).unwrap();
-    if time_since_cache < Duration::from_secs(config.update_interval) {
-      return Ok(load_cache(cache_path.to_path_buf()));
-    }
+fn save_cache(path: &Path, data: &WeatherData) -> Result<(), Box<dyn std::error::Error>> {
+  serde_json::to_writer(File::create(path)?, data)?;
+  Ok(())
+}
+
+/// Reads the API key without blocking the UI, as its command may wait for a password prompt.
+async fn read_api_key(config: &WeatherConfig) -> Result<String, String> {
+  let config = config.clone();
+  tokio::task::spawn_blocking(move || config.read_api_key())
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// `api_key` is read on the first request, and kept until it's rejected. It's read again then, in case it was rotated.
+async fn update_data(
+  config: &WeatherConfig,
+  api_key: &mut Option<String>,
+  cache_path: &Path,
+) -> Result<WeatherData, String> {
+  // No need to fetch data from server if cache time is close enough. A broken cache is fetched again.
+  match load_cache(cache_path, Duration::from_secs(config.update_interval)) {
+    Ok(Some(weather_data)) => return Ok(weather_data),
+    Ok(None) => {}
+    Err(err) => eprintln!("Failed to read the weather cache {}: {}", cache_path.display(), err),
   }
 
   // Update data from server
-  match get_weather(config.openweather_city_id, &config.openweather_api_key).await {
+  let key = match api_key {
+    Some(key) => key,
+    None => api_key.insert(read_api_key(config).await?),
+  };
+  match get_weather(config.openweather_city_id, key).await {
     Ok(weather_data) => {
-      let data_file = File::create(cache_path).unwrap();
-      serde_json::to_writer(data_file, &weather_data).unwrap();
+      if let Err(err) = save_cache(cache_path, &weather_data) {
+        eprintln!("Failed to write the weather cache {}: {}", cache_path.display(), err);
+      }
       Ok(weather_data)
     }
-    Err(error) => Err(error.to_string()),
+    Err(error) => {
+      if let Some(ApiError {
+        status: StatusCode::UNAUTHORIZED,
+        ..
+      }) = error.downcast_ref()
+      {
+        *api_key = None;
+      }
+      Err(error.to_string())
+    }
   }
 }
 
-pub fn weather_component() -> CursorArea {
+pub fn weather_component() -> Result<CursorArea, String> {
   let config = use_consume::<WeatherConfig>();
   let styles = use_consume::<WeatherStyles>();
   let global_styles = use_consume::<GlobalStyles>();
-  let cache_path = get_xdg_dirs().place_cache_file("weather.json").unwrap();
-
-  let mut has_err = use_state(|| false);
-  let mut error_str = use_state(|| "".into());
+  let metrics = use_consume::<Metrics>();
+  let cache_path = use_hook(|| {
+    get_xdg_dirs()
+      .place_cache_file("weather.json")
+      .map_err(|err| format!("Failed to create the cache directory: {}", err))
+  })?;
 
   let city_id = config.openweather_city_id;
   let mut data = use_state(WeatherData::default);
   let mut cond_icon = use_state(|| "");
+  let mut error = use_state(|| None::<String>);
 
   use_hook(|| {
     spawn(async move {
+      let mut api_key = None;
       loop {
-        let timeout = match update_data(&config, &cache_path).await {
+        let timeout = match update_data(&config, &mut api_key, &cache_path).await {
           Ok(data_) => {
+            metrics.set("weather", &data_);
+            // Unknown icons are left out
+            let cond_icon_key = data_
+              .weather
+              .first()
+              .map(|weather| weather.icon.chars().take(2).collect::<String>());
+            cond_icon.set(
+              cond_icon_key
+                .and_then(|key| ICON_MAP.get(key.as_str()).copied())
+                .unwrap_or(""),
+            );
             data.set(data_);
-            has_err.set(false);
-
-            let cond_icon_key = data.read().weather[0].icon.chars().take(2).collect::<String>();
-            cond_icon.set(ICON_MAP.get(cond_icon_key.as_str()).unwrap());
+            if error.read().is_some() {
+              error.set(None);
+            }
 
             config.update_interval
           }
-          Err(error_str_) => {
-            has_err.set(true);
-            error_str.set(error_str_);
+          Err(err) => {
+            error.set(Some(err));
             config.retry_timeout
           }
         };
@@ -96,52 +155,61 @@ pub fn weather_component() -> CursorArea {
       }
     });
   });
+  if let Some(error) = error.read().clone() {
+    return Err(error);
+  }
 
-  let timezone = FixedOffset::east_opt(data.read().timezone).unwrap();
+  let timezone = FixedOffset::east_opt(data.read().timezone).unwrap_or(Utc.fix());
 
   let center_cont = center_cont(global_styles.h_gap);
   let value_label = value_label_factory(*styles.value_color);
 
-  cursor_area(CursorIcon::Pointer).child(
-    rect()
-      .width(Size::percent(100.))
-      .padding(*styles.container_padding)
-      .on_pointer_press(move |_| {
-        // Open weather forecast link
-        open::that(format!("https://openweathermap.org/city/{0}#weather-widget", city_id)).unwrap();
-      })
-      .children(if has_err() || data.read().weather.is_empty() {
-        vec![center_cont.children(["Weather:".into_element(), error_str.read().to_string().into_element()])]
-      } else {
-        vec![
-          center_cont
-            .children([
-              emoji_label(cond_icon.read().to_string())
-                .font_size(styles.cond_icon_size)
+  Ok(
+    cursor_area(CursorIcon::Pointer).child(
+      rect()
+        .width(Size::percent(100.))
+        .padding(*styles.container_padding)
+        .on_pointer_press(move |_| {
+          // Open weather forecast link
+          let url = format!("https://openweathermap.org/city/{0}#weather-widget", city_id);
+          if let Err(err) = open::that(&url) {
+            eprintln!("Failed to open {}: {}", url, err);
+          }
+        })
+        .children(if data.read().weather.is_empty() {
+          // Until the first data arrives
+          vec![center_cont.children(["Weather:".into_element()])]
+        } else {
+          vec![
+            center_cont
+              .children([
+                emoji_label(cond_icon.read().to_string())
+                  .font_size(styles.cond_icon_size)
+                  .into_element(),
+                data.read().weather[0].description.to_title_case().into_element(),
+                value_label(format!("{:.0}°C", data.read().main.temp)).into_element(),
+              ])
+              .cross_align(Alignment::Center),
+            center_cont.children([
+              "Humidity".into_element(),
+              value_label(format!("{}%", data.read().main.humidity)).into_element(),
+              "Wind".into_element(),
+              value_label(format!("{:.1} m/s", data.read().wind.speed)).into_element(),
+              rect()
+                .margin(*styles.wind_arrow_margin)
+                .child(color_label(*styles.value_color, "⮕"))
+                // The wind degrees character used is `⮕`, which is at 90°.
+                .rotate(data.read().wind.deg - 90.)
                 .into_element(),
-              data.read().weather[0].description.to_title_case().into_element(),
-              value_label(format!("{:.0}°C", data.read().main.temp)).into_element(),
-            ])
-            .cross_align(Alignment::Center),
-          center_cont.children([
-            "Humidity".into_element(),
-            value_label(format!("{}%", data.read().main.humidity)).into_element(),
-            "Wind".into_element(),
-            value_label(format!("{:.1} m/s", data.read().wind.speed)).into_element(),
-            rect()
-              .margin(*styles.wind_arrow_margin)
-              .child(color_label(*styles.value_color, "⮕"))
-              // The wind degrees character used is `⮕`, which is at 90°.
-              .rotate(data.read().wind.deg - 90.)
-              .into_element(),
-          ]),
-          center_cont.children([
-            emoji_label("☀️"),
-            value_label(format_sun_timestamp(data.read().sys.sunrise, timezone)),
-            emoji_label("🌙"),
-            value_label(format_sun_timestamp(data.read().sys.sunset, timezone)),
-          ]),
-        ]
-      }),
+            ]),
+            center_cont.children([
+              emoji_label("☀️"),
+              value_label(format_sun_timestamp(data.read().sys.sunrise, timezone)),
+              emoji_label("🌙"),
+              value_label(format_sun_timestamp(data.read().sys.sunset, timezone)),
+            ]),
+          ]
+        }),
+    ),
   )
 }
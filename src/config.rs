@@ -1,78 +1,367 @@
 extern crate xdg;
 
-use std::error::Error;
-use std::fs::File;
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
 
-use serde_derive::Deserialize;
+use crate::config_file::{ConfigError, read_config_file};
+use crate::path::ConfigPaths;
+use crate::serde_structs::{SerdeCommand, SerdeGaps, SerdeRegex};
+use crate::utils::{expand_home, substitute_env_vars};
 
-use crate::path::get_xdg_dirs;
-use crate::serde_structs::{SerdeCommand, SerdeRegex};
+/// The weather panel, from *OpenWeather*, as written. Set one of the `openweather_api_key` options.
+#[derive(Deserialize, JsonSchema)]
+pub struct WeatherConfigFile {
+  /// Seconds. Forecasts are cached, and only refetched after this long.
+  pub update_interval: u64,
+  /// Seconds to wait after a failed request
+  pub retry_timeout: u64,
+  /// Environment variables are substituted, e.g. `${OPENWEATHER_API_KEY}`
+  pub openweather_api_key: Option<String>,
+  /// A file holding the key. Environment variables and a leading `~` are substituted.
+  pub openweather_api_key_file: Option<String>,
+  /// Prints the key, e.g. `pass show openweather`. Environment variables are substituted.
+  pub openweather_api_key_command: Option<SerdeCommand>,
+  /// From <https://openweathermap.org/find>
+  pub openweather_city_id: u64,
+}
+
+/// Where the *OpenWeather* API key is read from
+#[derive(Clone)]
+pub enum ApiKeySource {
+  Key(String),
+  File(String),
+  Command(SerdeCommand),
+}
 
-#[derive(Deserialize, Clone)]
+/// The weather panel, from *OpenWeather*. Set one of the `openweather_api_key` options.
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(try_from = "WeatherConfigFile")]
+#[schemars(with = "WeatherConfigFile")]
 pub struct WeatherConfig {
   pub update_interval: u64,
   pub retry_timeout: u64,
-  pub openweather_api_key: String,
+  pub api_key_source: ApiKeySource,
   pub openweather_city_id: u64,
 }
 
-#[derive(Deserialize, Clone)]
+impl TryFrom<WeatherConfigFile> for WeatherConfig {
+  type Error = &'static str;
+
+  fn try_from(file: WeatherConfigFile) -> Result<Self, Self::Error> {
+    let api_key_source = match (
+      file.openweather_api_key,
+      file.openweather_api_key_file,
+      file.openweather_api_key_command,
+    ) {
+      (Some(key), None, None) => ApiKeySource::Key(key),
+      (None, Some(path), None) => ApiKeySource::File(path),
+      (None, None, Some(command)) => ApiKeySource::Command(command),
+      (None, None, None) => {
+        return Err("Set one of openweather_api_key, openweather_api_key_file or openweather_api_key_command");
+      }
+      _ => {
+        return Err("Set only one of openweather_api_key, openweather_api_key_file and openweather_api_key_command");
+      }
+    };
+    Ok(WeatherConfig {
+      update_interval: file.update_interval,
+      retry_timeout: file.retry_timeout,
+      api_key_source,
+      openweather_city_id: file.openweather_city_id,
+    })
+  }
+}
+
+impl WeatherConfig {
+  /// Runs the command if that's the source, so call sparingly.
+  pub fn read_api_key(&self) -> Result<String, String> {
+    let key = match &self.api_key_source {
+      ApiKeySource::Key(key) => substitute_env_vars(key),
+      ApiKeySource::File(path) => {
+        let path = expand_home(&substitute_env_vars(path));
+        std::fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?
+      }
+      ApiKeySource::Command(command) => {
+        let command: Vec<String> = command.iter().map(|part| substitute_env_vars(part)).collect();
+        let (binary, args) = command.split_first().ok_or("Empty API key command")?;
+        let output = std::process::Command::new(binary)
+          .args(args)
+          .output()
+          .map_err(|err| format!("Failed to run {}: {}", binary, err))?;
+        if !output.status.success() {
+          return Err(format!("{} failed: {}", binary, output.status));
+        }
+        String::from_utf8(output.stdout).map_err(|err| err.to_string())?
+      }
+    };
+    Ok(key.trim().to_string())
+  }
+}
+
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct CpuMemoryProcessListConfig {
+  /// Processes shown, sorted by CPU and by memory usage
   pub num_processes: usize,
+  /// Run when clicking the process list. Environment variables are substituted.
   pub top_command: SerdeCommand,
 }
 
-#[derive(Deserialize, Clone)]
+/// Picks the CPU temperature sensor by label, or by file.
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureSensor {
+  /// Matched against the sensor labels, which are the *hwmon* name and the sensor's label, e.g. `k10temp Tccd1`. They're
+  /// listed on stderr when none matches.
+  Label(SerdeRegex),
+  /// Millidegrees Celsius, e.g. `/sys/class/hwmon/hwmon2/temp1_input`
+  Path(String),
+}
+
+/// The CPU, memory and process list panel.
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct CpuMemoryConfig {
+  /// Seconds
   pub update_interval: u64,
+  /// Detected when left out: *Intel* `coretemp`, *AMD* `k10temp` or `zenpower`, or an *ARM* CPU thermal zone
+  pub temperature_sensor: Option<TemperatureSensor>,
+  /// Shows the user, system, iowait, irq and steal time as text, besides the graph
+  #[serde(default)]
+  pub show_cpu_times: bool,
   pub process_list: CpuMemoryProcessListConfig,
 }
 
-#[derive(Deserialize, Clone)]
+/// The disk panel.
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct DiskConfig {
+  /// Seconds
   pub update_interval: u64,
+  /// Shown before the file system name
   pub name: String,
+  /// Used to look up the disk model with `udevadm`, e.g. `/dev/nvme0n1`
   pub device_path: String,
+  /// A *hwmon* temperature input, in millidegrees Celsius
   pub temperature_path: String,
   pub mount_point: String,
 }
 
-#[derive(Deserialize, Clone)]
+/// The *NVIDIA* GPU panel.
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct GpuConfig {
+  /// Seconds
   pub update_interval: u64,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct NetworkGraphProps {
+  /// The speed at which the graph is full
   pub maximum_bytes_per_sec: u64,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct NetworkGraphContainerProps {
   pub upload: NetworkGraphProps,
   pub download: NetworkGraphProps,
 }
 
-#[derive(Deserialize, Clone)]
+/// The network panel.
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct NetworkConfig {
+  /// Seconds
   pub update_interval: u64,
+  /// Seconds between public IP lookups. Leave out to not show the public IP.
   pub public_ip_retry_timeout: Option<u64>,
+  /// The first interface whose name matches is shown
   pub interface_regex: SerdeRegex,
   pub graphs: NetworkGraphContainerProps,
 }
 
-#[derive(Deserialize, Clone)]
+/// Picks an output by name, or by make, model and serial.
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum OutputSelector {
+  /// An output name, e.g. `DP-2`
+  Name(String),
+  /// Each one given has to match. Look them up with `swaymsg -t get_outputs`. Not available under *i3*.
+  Description {
+    make: Option<SerdeRegex>,
+    model: Option<SerdeRegex>,
+    serial: Option<SerdeRegex>,
+  },
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HorizontalAnchor {
+  Left,
+  #[default]
+  Right,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VerticalAnchor {
+  #[default]
+  Top,
+  Bottom,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HeightMode {
+  /// All of the height below the bars, less the margins
+  Fill,
+  /// The height of the panels
+  Fit,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum WindowHeight {
+  /// Pixels
+  Fixed(u32),
+  Mode(HeightMode),
+}
+
+impl Default for WindowHeight {
+  fn default() -> Self {
+    WindowHeight::Mode(HeightMode::Fill)
+  }
+}
+
+/// A workspace by number or by name.
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum WorkspaceSelector {
+  /// The number a workspace name starts with, e.g. `1` for `1: web`
+  Number(i32),
+  Name(String),
+}
+
+/// When the window is shown. Otherwise, it's moved to the scratchpad.
+#[derive(Deserialize, JsonSchema, Clone, Default)]
+#[serde(default)]
+pub struct VisibilityConfig {
+  /// Shown only on these workspaces. All of them when left out.
+  pub workspaces: Option<Vec<WorkspaceSelector>>,
+  /// Hidden while the workspace has a fullscreen window, e.g. a video player or a game
+  pub hide_on_fullscreen: bool,
+}
+
+/// Where the window is placed.
+#[derive(Deserialize, JsonSchema, Clone, Default)]
+#[serde(default)]
+pub struct WindowPlacementConfig {
+  /// The focused output is used when left out, or when no output matches.
+  pub output: Option<OutputSelector>,
+  pub horizontal_anchor: HorizontalAnchor,
+  pub vertical_anchor: VerticalAnchor,
+  /// From the edges of the area below the bars
+  pub margin: SerdeGaps,
+  /// Never more than fits below the bars
+  pub height: WindowHeight,
+  /// Rules are checked against the workspace shown on the window's output
+  pub visibility: VisibilityConfig,
+}
+
+/// A panel or separator in the window, listed top to bottom under `layout`.
+#[derive(Deserialize, Serialize, JsonSchema, ValueEnum, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum LayoutItem {
+  Weather,
+  MachineInfo,
+  CpuMemory,
+  Disk,
+  Gpu,
+  Network,
+  Separator,
+}
+
+fn default_layout() -> Vec<LayoutItem> {
+  use LayoutItem::*;
+  vec![
+    Weather,
+    Separator,
+    MachineInfo,
+    Separator,
+    CpuMemory,
+    Separator,
+    Disk,
+    Separator,
+    Gpu,
+    Separator,
+    Network,
+  ]
+}
+
+/// Serves the metrics at `/metrics` in the OpenMetrics text format, e.g. for *Prometheus*. Only read at startup.
+#[derive(Deserialize, JsonSchema, Clone)]
+pub struct ExporterConfig {
+  /// Address and port, e.g. `127.0.0.1:9123`
+  pub listen: String,
+}
+
+/// `dogky.yaml`
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct DogkyConfig {
-  pub weather: WeatherConfig,
-  pub cpu_memory: CpuMemoryConfig,
-  pub disk: DiskConfig,
-  pub gpu: GpuConfig,
-  pub network: NetworkConfig,
-}
-
-pub fn load_config() -> Result<DogkyConfig, Box<dyn Error>> {
-  let config_path = get_xdg_dirs().place_config_file("dogky.yaml")?;
-  let config_file = File::open(config_path)?;
-  let config: DogkyConfig = noyalib::from_reader(config_file)?;
-  Ok(config)
+  /// Panels, top to bottom. Panels can be left out, and separators repeated.
+  #[serde(default = "default_layout")]
+  pub layout: Vec<LayoutItem>,
+  #[serde(default)]
+  pub window: WindowPlacementConfig,
+  /// Off when left out
+  pub exporter: Option<ExporterConfig>,
+  // A missing section disables its panel
+  pub weather: Option<WeatherConfig>,
+  pub cpu_memory: Option<CpuMemoryConfig>,
+  pub disk: Option<DiskConfig>,
+  pub gpu: Option<GpuConfig>,
+  pub network: Option<NetworkConfig>,
+}
+
+pub fn load_config(paths: &ConfigPaths) -> Result<DogkyConfig, ConfigError> {
+  read_config_file(&paths.config)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cli::DEFAULT_CONFIG;
+
+  #[test]
+  fn default_config_file_loads() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dogky.yaml");
+    std::fs::write(&path, DEFAULT_CONFIG).unwrap();
+    let config: Result<DogkyConfig, _> = read_config_file(&path);
+    assert_eq!(config.unwrap().layout, default_layout());
+  }
+
+  #[test]
+  fn weather_needs_exactly_one_api_key_source() {
+    let weather = |keys: &str| {
+      noyalib::from_str::<WeatherConfig>(&format!(
+        "update_interval: 600\nretry_timeout: 30\nopenweather_city_id: 1\n{}",
+        keys
+      ))
+    };
+    assert!(matches!(
+      weather("openweather_api_key_file: ~/key\n").unwrap().api_key_source,
+      ApiKeySource::File(path) if path == "~/key"
+    ));
+    assert!(weather("").is_err());
+    assert!(weather("openweather_api_key: key\nopenweather_api_key_command: pass key\n").is_err());
+  }
+
+  #[test]
+  fn temperature_sensor_errors_name_the_option() {
+    let sensor = |sensor: &str| noyalib::from_str::<TemperatureSensor>(sensor).map_err(|err| err.to_string());
+    assert!(matches!(
+      sensor("path: /sys/class/hwmon/hwmon2/temp1_input"),
+      Ok(TemperatureSensor::Path(_))
+    ));
+    assert!(sensor("label: \"(\"").is_err_and(|err| err.contains("unclosed group")));
+    assert!(sensor("lable: k10temp").is_err_and(|err| err.contains("unknown variant `lable`")));
+  }
 }
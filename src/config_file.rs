@@ -0,0 +1,290 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use noyalib::{Location, Mapping, Value};
+use serde::de::DeserializeOwned;
+
+/// A config file that couldn't be read, or doesn't match its config struct.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigError {
+  pub path: PathBuf,
+  pub message: String,
+  pub location: Option<Location>,
+  /// The offending line, shown under the message
+  pub line: Option<String>,
+}
+
+impl ConfigError {
+  pub fn new(path: &Path, message: impl ToString) -> Self {
+    ConfigError {
+      path: path.to_path_buf(),
+      message: message.to_string(),
+      location: None,
+      line: None,
+    }
+  }
+
+  fn with_location(mut self, source: &str, location: Location) -> Self {
+    self.line = source
+      .lines()
+      .nth(location.line().saturating_sub(1))
+      .map(str::to_string);
+    self.location = Some(location);
+    self
+  }
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.path.display())?;
+    if let Some(location) = self.location {
+      write!(f, ":{}:{}", location.line(), location.column())?;
+    }
+    write!(f, ": {}", self.message)?;
+    if let (Some(location), Some(line)) = (self.location, &self.line) {
+      write!(f, "\n  {}\n  {: >2$}", line, "^", location.column())?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Errors from deserializing a `Value` have no location, so look it up from the key path in the source.
+fn find_location(source: &str, key_path: &str) -> Option<Location> {
+  let document = noyalib::cst::parse_document(source).ok()?;
+  let (start, _end) = document.span_at(key_path)?;
+  Some(Location::from_index(source, start))
+}
+
+/// Reads a *YAML* file into a `Value`. An empty file is read as an empty mapping.
+fn read_value(path: &Path) -> Result<(Value, String), ConfigError> {
+  let source = std::fs::read_to_string(path).map_err(|err| ConfigError::new(path, err))?;
+  let value: Value = noyalib::from_str(&source).map_err(|err| {
+    let error = ConfigError::new(path, err.to_string());
+    match err.location() {
+      Some(location) => error.with_location(&source, location),
+      None => error,
+    }
+  })?;
+  if value.is_null() {
+    return Ok((Value::Mapping(Mapping::new()), source));
+  }
+  Ok((value, source))
+}
+
+/// Merges mappings key by key. Anything else, lists included, is replaced.
+pub fn merge(base: &mut Value, overlay: Value) {
+  match (base, overlay) {
+    (Value::Mapping(base), Value::Mapping(overlay)) => {
+      for (key, value) in overlay {
+        match base.get_mut(&key) {
+          Some(base_value) => merge(base_value, value),
+          None => {
+            base.insert(key, value);
+          }
+        }
+      }
+    }
+    (base, overlay) => *base = overlay,
+  }
+}
+
+/// The files read for one config, in the order they were read. The top file comes first.
+struct Sources(Vec<(PathBuf, String)>);
+
+impl Sources {
+  /// Points at the first file with any of the key paths, tried in order. Falls back to the top file.
+  fn error_at(&self, key_paths: &[&str], message: impl ToString) -> ConfigError {
+    self
+      .0
+      .iter()
+      .find_map(|(path, source)| {
+        let location = key_paths.iter().find_map(|key_path| find_location(source, key_path))?;
+        Some(ConfigError::new(path, message.to_string()).with_location(source, location))
+      })
+      .unwrap_or_else(|| ConfigError::new(&self.0[0].0, message))
+  }
+}
+
+/// Reads a file, merged over the files it lists under `include`. Included paths are relative to the including file.
+fn read_with_includes(path: &Path, sources: &mut Sources, stack: &mut Vec<PathBuf>) -> Result<Value, ConfigError> {
+  let canonical_path = path.canonicalize().unwrap_or(path.to_path_buf());
+  if stack.contains(&canonical_path) {
+    return Err(ConfigError::new(
+      path,
+      "Includes itself, directly or through other files",
+    ));
+  }
+  let (mut value, source) = read_value(path)?;
+  let include = value.as_mapping_mut().and_then(|mapping| mapping.remove("include"));
+  sources.0.push((path.to_path_buf(), source));
+  let Some(include) = include else {
+    return Ok(value);
+  };
+
+  let error = || {
+    let (path, source) = sources.0.last().unwrap();
+    let error = ConfigError::new(path, "include: expected a path or a list of paths");
+    match find_location(source, "include") {
+      Some(location) => error.with_location(source, location),
+      None => error,
+    }
+  };
+  let include_paths: Vec<String> = match include {
+    Value::String(include_path) => vec![include_path],
+    Value::Sequence(include_paths) => include_paths
+      .into_iter()
+      .map(|include_path| match include_path {
+        Value::String(include_path) => Ok(include_path),
+        _ => Err(error()),
+      })
+      .collect::<Result<_, _>>()?,
+    _ => return Err(error()),
+  };
+
+  let dir = path.parent().unwrap_or(Path::new(""));
+  let mut merged = Value::Mapping(Mapping::new());
+  stack.push(canonical_path);
+  for include_path in include_paths {
+    merge(
+      &mut merged,
+      read_with_includes(&dir.join(include_path), sources, stack)?,
+    );
+  }
+  stack.pop();
+  merge(&mut merged, value);
+  Ok(merged)
+}
+
+/// The files read for a config file: itself, then its includes, recursively. A file that fails to read is listed too,
+/// and ends the list.
+pub fn list_config_files(path: &Path) -> Vec<PathBuf> {
+  let mut sources = Sources(vec![]);
+  let result = read_with_includes(path, &mut sources, &mut vec![]);
+  let mut files: Vec<PathBuf> = sources.0.into_iter().map(|(path, _source)| path).collect();
+  if let Err(err) = result
+    && !files.contains(&err.path)
+  {
+    files.push(err.path);
+  }
+  files
+}
+
+/// Merges the override under `hosts` that matches this machine's hostname.
+fn apply_host_overrides(value: &mut Value, hostname: Option<&str>, sources: &Sources) -> Result<(), ConfigError> {
+  let Some(hosts) = value.as_mapping_mut().and_then(|mapping| mapping.remove("hosts")) else {
+    return Ok(());
+  };
+  let Value::Mapping(mut hosts) = hosts else {
+    return Err(sources.error_at(&["hosts"], "hosts: expected a mapping of hostnames"));
+  };
+  if let Some(host_override) = hostname.and_then(|hostname| hosts.remove(hostname)) {
+    merge(value, host_override);
+  }
+  Ok(())
+}
+
+/// An error from preprocessing a `Value`, at a key path in it
+pub struct ValueError {
+  pub key_path: String,
+  pub message: String,
+}
+
+/// Reads a *YAML* file into `T`, after merging its includes and host overrides. An empty file is read as an empty
+/// mapping.
+pub fn read_config_file<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+  read_preprocessed_config_file(path, |_value| Ok(()))
+}
+
+/// Like `read_config_file`, with a change to the merged `Value` before it's deserialized.
+pub fn read_preprocessed_config_file<T: DeserializeOwned>(
+  path: &Path,
+  preprocess: impl FnOnce(&mut Value) -> Result<(), ValueError>,
+) -> Result<T, ConfigError> {
+  let mut sources = Sources(vec![]);
+  let mut value = read_with_includes(path, &mut sources, &mut vec![])?;
+  let hostname = whoami::hostname().ok();
+  apply_host_overrides(&mut value, hostname.as_deref(), &sources)?;
+
+  // The value may come from a host override
+  let error_at = |key_path: String, message: String| match &hostname {
+    Some(hostname) => sources.error_at(&[&format!("hosts.{}.{}", hostname, key_path), &key_path], message),
+    None => sources.error_at(&[&key_path], message),
+  };
+  preprocess(&mut value).map_err(|err| error_at(err.key_path, err.message))?;
+  serde_path_to_error::deserialize(noyalib::Deserializer::new(&value)).map_err(|err| {
+    let key_path = err.path().to_string();
+    let message = format!("{}: {}", key_path, err.inner());
+    error_at(key_path, message)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn yaml(source: &str) -> Value {
+    noyalib::from_str(source).unwrap()
+  }
+
+  #[test]
+  fn merge_prefers_the_overlay() {
+    let mut base = yaml("a: 1\nnested:\n  b: 2\n  c: 3\nlist: [1, 2]\n");
+    merge(&mut base, yaml("nested:\n  c: 4\nlist: [5]\nd: 6\n"));
+    assert_eq!(base, yaml("a: 1\nnested:\n  b: 2\n  c: 4\nlist: [5]\nd: 6\n"));
+  }
+
+  #[test]
+  fn includes_are_merged_in_order_under_the_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a.yaml"), "x: a\ny: a\nz: a\n").unwrap();
+    std::fs::write(dir.join("b.yaml"), "y: b\nz: b\n").unwrap();
+    std::fs::write(dir.join("dogky.yaml"), "include: [a.yaml, b.yaml]\nz: top\n").unwrap();
+    let value = read_with_includes(&dir.join("dogky.yaml"), &mut Sources(vec![]), &mut vec![]).unwrap();
+    assert_eq!(value, yaml("x: a\ny: b\nz: top\n"));
+    assert_eq!(
+      list_config_files(&dir.join("dogky.yaml")),
+      [dir.join("dogky.yaml"), dir.join("a.yaml"), dir.join("b.yaml")]
+    );
+  }
+
+  #[test]
+  fn include_cycles_are_errors() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("dogky.yaml"), "include: base.yaml\n").unwrap();
+    std::fs::write(dir.join("base.yaml"), "include: dogky.yaml\n").unwrap();
+    let err = read_with_includes(&dir.join("dogky.yaml"), &mut Sources(vec![]), &mut vec![]).unwrap_err();
+    assert_eq!(err.path, dir.join("dogky.yaml"));
+    assert!(err.message.contains("Includes itself"));
+  }
+
+  #[test]
+  fn missing_includes_are_listed() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("dogky.yaml"), "include: [missing.yaml, other.yaml]\n").unwrap();
+    assert_eq!(
+      list_config_files(&dir.join("dogky.yaml")),
+      [dir.join("dogky.yaml"), dir.join("missing.yaml")]
+    );
+  }
+
+  #[test]
+  fn host_overrides_are_merged_over_the_file() {
+    let source = "a: 1\nb: 1\nhosts:\n  desk:\n    b: 2\n  laptop:\n    b: 3\n";
+    let sources = Sources(vec![(PathBuf::from("dogky.yaml"), source.to_string())]);
+    let mut value = yaml(source);
+    apply_host_overrides(&mut value, Some("desk"), &sources).unwrap();
+    assert_eq!(value, yaml("a: 1\nb: 2\n"));
+
+    let mut value = yaml(source);
+    apply_host_overrides(&mut value, Some("server"), &sources).unwrap();
+    assert_eq!(value, yaml("a: 1\nb: 1\n"));
+
+    let mut value = yaml("hosts: [desk]\n");
+    assert!(apply_host_overrides(&mut value, Some("desk"), &sources).is_err());
+  }
+}
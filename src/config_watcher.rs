@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use futures_lite::{Stream, StreamExt};
+use inotify::{Inotify, WatchDescriptor, WatchMask, Watches};
+
+use crate::config_file::list_config_files;
+use crate::path::ConfigPaths;
+
+const EVENT_BUFFER_SIZE: usize = 4096;
+
+/// With the directory canonicalized, to match the directory events come from. Files don't have to exist.
+fn normalize(path: PathBuf) -> PathBuf {
+  match (path.parent().and_then(|dir| dir.canonicalize().ok()), path.file_name()) {
+    (Some(dir), Some(file_name)) => dir.join(file_name),
+    _ => path,
+  }
+}
+
+/// The config files, and the files they include as of now
+fn list_watched_files(paths: &ConfigPaths) -> HashSet<PathBuf> {
+  paths.files().flat_map(list_config_files).map(normalize).collect()
+}
+
+struct Watcher {
+  watches: Watches,
+  dirs: HashMap<WatchDescriptor, PathBuf>,
+  files: HashSet<PathBuf>,
+}
+
+impl Watcher {
+  /// Watches the directories of files that aren't watched yet. Directories that can't be watched, e.g. ones that
+  /// don't exist, are skipped.
+  fn watch(&mut self, files: HashSet<PathBuf>) {
+    for dir in files.iter().filter_map(|file| file.parent()) {
+      if self.dirs.values().any(|watched_dir| watched_dir == dir) {
+        continue;
+      }
+      match self.watches.add(
+        dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE,
+      ) {
+        Ok(descriptor) => {
+          self.dirs.insert(descriptor, dir.to_path_buf());
+        }
+        Err(err) => eprintln!("Not watching {}: {}", dir.display(), err),
+      }
+    }
+    self.files = files;
+  }
+
+  fn is_watched(&self, descriptor: &WatchDescriptor, file_name: &Path) -> bool {
+    self
+      .dirs
+      .get(descriptor)
+      .is_some_and(|dir| self.files.contains(&dir.join(file_name)))
+  }
+}
+
+/// Yields whenever one of the config files, or a file they include, is written, replaced or removed. The included files
+/// are listed again after each change, to follow added and removed includes.
+///
+/// The directories are watched rather than the files, to see files that editors replace and files that don't exist yet.
+/// Editors often save with several file operations, so a single save can yield more than once.
+pub fn watch_config_files(paths: &ConfigPaths) -> Result<impl Stream<Item = ()> + use<>, Box<dyn Error>> {
+  let inotify = Inotify::init()?;
+  let mut watcher = Watcher {
+    watches: inotify.watches(),
+    dirs: HashMap::new(),
+    files: HashSet::new(),
+  };
+  watcher.watch(list_watched_files(paths));
+  let paths = paths.clone();
+  // Needs to be called within the *Tokio* runtime
+  let events = inotify.into_event_stream([0u8; EVENT_BUFFER_SIZE])?;
+  Ok(events.filter_map(move |event| {
+    let event = event.ok()?;
+    if !watcher.is_watched(&event.wd, Path::new(&event.name?)) {
+      return None;
+    }
+    watcher.watch(list_watched_files(&paths));
+    Some(())
+  }))
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+use async_io::{Async, Timer};
+use clap::Subcommand;
+use futures_lite::io::BufReader;
+use futures_lite::{AsyncBufReadExt, AsyncWriteExt, future};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::LayoutItem;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Before accepting connections again, after failing to
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A request to the running instance. Sent as a line of *JSON*.
+#[derive(Subcommand, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+  /// Shows the window, if the visibility rules allow it
+  Show,
+  /// Hides the window in the scratchpad
+  Hide,
+  /// Hides the window, or shows it again
+  Toggle,
+  /// Reloads the config files
+  Reload,
+  /// Restarts a panel, which samples it right away
+  Refresh {
+    #[arg(value_enum)]
+    panel: LayoutItem,
+  },
+  /// Prints the latest sample of each collector as JSON, by collector name
+  Metrics,
+}
+
+/// The answer to a `Message`. Sent as *JSON*, before the connection closes.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+  Done,
+  Metrics(serde_json::Value),
+  Error(String),
+}
+
+/// Listens on the socket. Returns `None` when another instance already does. A socket left behind by an instance that
+/// died is replaced.
+pub fn bind(path: &Path) -> io::Result<Option<UnixListener>> {
+  if path.exists() {
+    if UnixStream::connect(path).is_ok() {
+      return Ok(None);
+    }
+    std::fs::remove_file(path)?;
+  }
+  UnixListener::bind(path).map(Some)
+}
+
+/// Sends a message to the instance listening on the socket.
+pub fn send(path: &Path, message: &Message) -> Result<Reply, Box<dyn Error>> {
+  let mut stream =
+    UnixStream::connect(path).map_err(|err| format!("No dogky running at {}: {}", path.display(), err))?;
+  stream.write_all(format!("{}\n", serde_json::to_string(message)?).as_bytes())?;
+  stream.shutdown(std::net::Shutdown::Write)?;
+  let mut reply = String::new();
+  stream.read_to_string(&mut reply)?;
+  Ok(serde_json::from_str(&reply)?)
+}
+
+/// Messages are answered in turn, so don't let a stalled client hold up the rest
+async fn with_timeout<T>(io: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+  future::or(io, async {
+    Timer::after(REQUEST_TIMEOUT).await;
+    Err(io::Error::new(io::ErrorKind::TimedOut, "The client stalled"))
+  })
+  .await
+}
+
+async fn serve_connection(
+  stream: Async<UnixStream>,
+  handle: &mut impl AsyncFnMut(Message) -> Reply,
+) -> Result<(), Box<dyn Error>> {
+  let mut line = String::new();
+  with_timeout(BufReader::new(&stream).read_line(&mut line)).await?;
+  // Another instance checking whether this one runs
+  if line.is_empty() {
+    return Ok(());
+  }
+  let reply = match serde_json::from_str(&line) {
+    Ok(message) => handle(message).await,
+    Err(err) => Reply::Error(format!("Invalid message: {}", err)),
+  };
+  with_timeout((&stream).write_all(serde_json::to_string(&reply)?.as_bytes())).await?;
+  Ok(())
+}
+
+/// Answers messages one connection at a time, with `handle`.
+pub async fn serve(
+  listener: UnixListener,
+  mut handle: impl AsyncFnMut(Message) -> Reply,
+) -> Result<(), Box<dyn Error>> {
+  let listener = Async::new(listener)?;
+  loop {
+    let stream = match listener.accept().await {
+      Ok((stream, _address)) => stream,
+      // E.g. out of file descriptors, until some are closed
+      Err(err) => {
+        eprintln!("Failed to accept a connection: {}", err);
+        Timer::after(ACCEPT_RETRY_DELAY).await;
+        continue;
+      }
+    };
+    if let Err(err) = serve_connection(stream, &mut handle).await {
+      eprintln!("Failed to answer a message: {}", err);
+    }
+  }
+}
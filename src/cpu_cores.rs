@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde_derive::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoreKind {
+  Performance,
+  Efficient,
+}
+
+impl CoreKind {
+  pub fn name(&self) -> &'static str {
+    match self {
+      CoreKind::Performance => "performance",
+      CoreKind::Efficient => "efficient",
+    }
+  }
+}
+
+/// Parses a kernel CPU list, e.g. `0-7,16-23`, into CPU numbers. Also accepts strides, e.g. `0-15:2/4`.
+pub fn parse_cpulist(list: &str) -> Result<Vec<usize>, String> {
+  let parse_number = |number: &str| {
+    number
+      .parse::<usize>()
+      .map_err(|err| format!("Invalid CPU list {:?}: {}", list, err))
+  };
+  let mut cpus = vec![];
+  for group in list.trim().split(',').filter(|group| !group.is_empty()) {
+    let (range, stride) = match group.split_once(':') {
+      Some((range, stride)) => (range, Some(stride)),
+      None => (group, None),
+    };
+    let (first, last) = match range.split_once('-') {
+      Some((first, last)) => (parse_number(first)?, parse_number(last)?),
+      None => {
+        let cpu = parse_number(range)?;
+        (cpu, cpu)
+      }
+    };
+    // Of every `group_size` CPUs, the first `used` are listed
+    let (used, group_size) = match stride.map(|stride| stride.split_once('/')) {
+      None => (1, 1),
+      Some(Some((used, group_size))) => (parse_number(used)?, parse_number(group_size)?),
+      Some(None) => return Err(format!("Invalid CPU list {:?}", list)),
+    };
+    if group_size == 0 {
+      return Err(format!("Invalid CPU list {:?}", list));
+    }
+    cpus.extend((first..=last).filter(|cpu| (cpu - first) % group_size < used));
+  }
+  Ok(cpus)
+}
+
+/// `None` when the file doesn't exist or doesn't parse
+fn read_cpulist(path: impl AsRef<Path>) -> Option<HashSet<usize>> {
+  let list = std::fs::read_to_string(path).ok()?;
+  parse_cpulist(&list)
+    .inspect_err(|err| eprintln!("{}", err))
+    .ok()
+    .map(HashSet::from_iter)
+}
+
+/// Intel hybrid CPUs list their performance and efficient cores under separate PMUs
+fn get_intel_hybrid_kinds(num_cpus: usize) -> Option<Vec<CoreKind>> {
+  let kind = |is_efficient: bool| {
+    if is_efficient {
+      CoreKind::Efficient
+    } else {
+      CoreKind::Performance
+    }
+  };
+  if let Some(atom_cpus) = read_cpulist("/sys/devices/cpu_atom/cpus") {
+    return Some((0..num_cpus).map(|cpu| kind(atom_cpus.contains(&cpu))).collect());
+  }
+  let core_cpus = read_cpulist("/sys/devices/cpu_core/cpus")?;
+  Some((0..num_cpus).map(|cpu| kind(!core_cpus.contains(&cpu))).collect())
+}
+
+/// The cores with the lowest capacity are efficient, the rest perform. `None` when they're all the same.
+fn classify_capacities(capacities: &[u32]) -> Option<Vec<CoreKind>> {
+  let lowest_capacity = *capacities.iter().min()?;
+  if capacities.iter().all(|&capacity| capacity == lowest_capacity) {
+    return None;
+  }
+  Some(
+    capacities
+      .iter()
+      .map(|&capacity| {
+        if capacity == lowest_capacity {
+          CoreKind::Efficient
+        } else {
+          CoreKind::Performance
+        }
+      })
+      .collect(),
+  )
+}
+
+/// *ARM* big.LITTLE, and other CPUs the kernel knows the relative performance of
+fn get_capacity_kinds(num_cpus: usize) -> Option<Vec<CoreKind>> {
+  let capacities = (0..num_cpus)
+    .map(|cpu| {
+      let capacity = std::fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/cpu_capacity", cpu)).ok()?;
+      capacity.trim().parse::<u32>().ok()
+    })
+    .collect::<Option<Vec<u32>>>()?;
+  classify_capacities(&capacities)
+}
+
+/// The kind of each logical CPU, in order. All of them perform on CPUs that aren't hybrid.
+pub fn get_core_kinds(num_cpus: usize) -> Vec<CoreKind> {
+  get_intel_hybrid_kinds(num_cpus)
+    .or_else(|| get_capacity_kinds(num_cpus))
+    .unwrap_or_else(|| vec![CoreKind::Performance; num_cpus])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_cpulists() {
+    assert_eq!(parse_cpulist("0-3,8,10-11\n"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+    assert_eq!(parse_cpulist(""), Ok(vec![]));
+  }
+
+  #[test]
+  fn parses_cpulist_strides() {
+    assert_eq!(parse_cpulist("0-15:2/4"), Ok(vec![0, 1, 4, 5, 8, 9, 12, 13]));
+    assert_eq!(parse_cpulist("0-7:1/2,9"), Ok(vec![0, 2, 4, 6, 9]));
+  }
+
+  #[test]
+  fn backwards_cpulist_ranges_are_empty() {
+    assert_eq!(parse_cpulist("7-3"), Ok(vec![]));
+  }
+
+  #[test]
+  fn invalid_cpulists_are_errors() {
+    for list in ["a", "0-", "0-7:2", "0-7:1/0", "-1"] {
+      assert!(parse_cpulist(list).is_err(), "{:?}", list);
+    }
+  }
+
+  #[test]
+  fn lowest_capacity_cores_are_efficient() {
+    use CoreKind::*;
+    assert_eq!(
+      classify_capacities(&[1024, 1024, 446, 446]),
+      Some(vec![Performance, Performance, Efficient, Efficient])
+    );
+    assert_eq!(classify_capacities(&[1024, 1024]), None);
+    assert_eq!(classify_capacities(&[]), None);
+  }
+}
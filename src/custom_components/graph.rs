@@ -1,19 +1,22 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::{any::Any, borrow::Cow};
 
-use circular_queue::CircularQueue;
 use freya::prelude::*;
 use freya_core::{element::ElementExt, tree::DiffModifies};
 use freya_engine::prelude::PathBuilder;
 use freya_engine::prelude::{Paint, PaintStyle, Point};
 
+use crate::history::History;
+
 static BORDER_WIDTH: f32 = 1.;
 
 #[derive(PartialEq)]
 pub struct Graph<const N: usize> {
   layout_data: LayoutData,
-  datasets: [CircularQueue<f32>; N],
+  datasets: [History; N],
   colors: [Color; N],
+  stacked: bool,
 }
 
 impl<const N: usize> ElementExt for Graph<N> {
@@ -22,7 +25,7 @@ impl<const N: usize> ElementExt for Graph<N> {
       return DiffModifies::all();
     };
     let mut diff = DiffModifies::empty();
-    if self.colors != element.colors || self.datasets != element.datasets {
+    if self.colors != element.colors || self.datasets != element.datasets || self.stacked != element.stacked {
       diff.insert(DiffModifies::STYLE);
     }
     if self.layout_data != element.layout_data {
@@ -48,33 +51,56 @@ impl<const N: usize> ElementExt for Graph<N> {
     );
     let height = max_y - min_y;
 
-    for (i, (dataset, &color)) in self.datasets.iter().zip(self.colors.iter()).enumerate() {
-      if i == 0 {
-        paint.set_style(PaintStyle::Fill);
-        paint.set_color(color);
-      } else if i == 1 {
-        paint.set_style(PaintStyle::Stroke);
-        paint.set_color(color);
-      }
+    let series: Vec<Vec<(usize, f32)>> = if self.stacked {
+      stack(&self.datasets)
+    } else {
+      self
+        .datasets
+        .iter()
+        .map(|dataset| dataset.iter_slots().collect())
+        .collect()
+    };
+    // The topmost stacked series is drawn first, and each one below covers it down to the bottom
+    let order: Vec<usize> = if self.stacked {
+      (0..N).rev().collect()
+    } else {
+      (0..N).collect()
+    };
+
+    for i in order {
+      // Without stacking, the first dataset is filled and the rest are lines. Each run of samples without a gap is
+      // its own shape.
+      let is_filled = self.stacked || i == 0;
+      paint.set_style(if is_filled {
+        PaintStyle::Fill
+      } else {
+        PaintStyle::Stroke
+      });
+      paint.set_color(self.colors[i]);
       let mut path = PathBuilder::new();
-      let mut x = 0f32;
-      if i == 0 {
-        x = max_x;
-        path.move_to(Point::new(x, max_y));
-      }
-      for (j, value) in dataset.iter().enumerate() {
-        x = max_x - j as f32;
+      let mut last: Option<(usize, f32)> = None;
+      for &(slot, value) in &series[i] {
+        let x = max_x - slot as f32;
         if x < min_x {
           break;
         }
-        if i == 1 && j == 0 {
-          path.move_to(Point::new(x, max_y - height * value));
+        let point = Point::new(x, max_y - height * value);
+        let starts_run = last.is_none_or(|(last_slot, _last_x)| slot > last_slot + 1);
+        if !starts_run {
+          path.line_to(point);
+        } else if is_filled {
+          if let Some((_last_slot, last_x)) = last {
+            path.line_to(Point::new(last_x, max_y));
+          }
+          path.move_to(Point::new(x, max_y));
+          path.line_to(point);
         } else {
-          path.line_to(Point::new(x, max_y - height * value));
+          path.move_to(point);
         }
+        last = Some((slot, x));
       }
-      if i == 0 {
-        path.line_to(Point::new(x, max_y));
+      if is_filled && let Some((_last_slot, last_x)) = last {
+        path.line_to(Point::new(last_x, max_y));
       }
       let path = path.detach();
       context.canvas.draw_path(&path, &paint);
@@ -82,6 +108,24 @@ impl<const N: usize> ElementExt for Graph<N> {
   }
 }
 
+/// Each dataset's slots, with the values of the datasets before it added
+fn stack<const N: usize>(datasets: &[History; N]) -> Vec<Vec<(usize, f32)>> {
+  let mut totals = HashMap::<usize, f32>::new();
+  datasets
+    .iter()
+    .map(|dataset| {
+      dataset
+        .iter_slots()
+        .map(|(slot, value)| {
+          let total = totals.entry(slot).or_default();
+          *total += value;
+          (slot, *total)
+        })
+        .collect()
+    })
+    .collect()
+}
+
 impl<const N: usize> LayoutExt for Graph<N> {
   fn get_layout(&mut self) -> &mut LayoutData {
     &mut self.layout_data
@@ -100,12 +144,21 @@ impl<const N: usize> From<Graph<N>> for Element {
   }
 }
 
-pub fn create_graph<const N: usize, C: Into<Color>>(datasets: [CircularQueue<f32>; N], colors: [C; N]) -> Graph<N> {
+pub fn create_graph<const N: usize, C: Into<Color>>(datasets: [History; N], colors: [C; N]) -> Graph<N> {
   Graph {
     layout_data: LayoutData::default(),
     datasets,
     colors: colors.map(|c| c.into()),
+    stacked: false,
   }
   .width(Size::flex(1.))
   .height(Size::flex(1.))
 }
+
+/// Every dataset is filled, on top of the ones before it
+pub fn create_stacked_graph<const N: usize, C: Into<Color>>(datasets: [History; N], colors: [C; N]) -> Graph<N> {
+  Graph {
+    stacked: true,
+    ..create_graph(datasets, colors)
+  }
+}
@@ -1,5 +1,5 @@
 mod graph;
 mod separator;
 
-pub use graph::create_graph;
-pub use separator::create_separator;
+pub use graph::{create_graph, create_stacked_graph};
+pub use separator::{Separator, create_separator};
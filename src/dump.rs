@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nvml_wrapper::Nvml;
+use serde_json::{Map, Value, json};
+
+use crate::collectors::{
+  Collector, CpuCollector, DiskCollector, GpuCollector, MemoryCollector, NetworkCollector, ProcessCollector,
+  try_collect,
+};
+use crate::config::DogkyConfig;
+
+/// A collector with its samples as *JSON*, so that collectors of different kinds fit in one list
+trait JsonCollector {
+  fn name(&self) -> &'static str;
+
+  fn interval(&self) -> Duration;
+
+  /// A failed collection is an object with an `error`
+  fn collect_json(&mut self) -> Value;
+}
+
+impl<C: Collector> JsonCollector for C {
+  fn name(&self) -> &'static str {
+    C::NAME
+  }
+
+  fn interval(&self) -> Duration {
+    Collector::interval(self)
+  }
+
+  fn collect_json(&mut self) -> Value {
+    let sample = try_collect(self).and_then(|sample| serde_json::to_value(sample).map_err(|err| err.to_string()));
+    sample.unwrap_or_else(|err| json!({ "error": err }))
+  }
+}
+
+/// The collectors of the panels in the config
+fn get_collectors(config: &DogkyConfig) -> Vec<Box<dyn JsonCollector>> {
+  let mut collectors: Vec<Box<dyn JsonCollector>> = vec![];
+  if let Some(cpu_memory) = &config.cpu_memory {
+    let interval = Duration::from_secs(cpu_memory.update_interval);
+    collectors.push(Box::new(CpuCollector::new(cpu_memory)));
+    collectors.push(Box::new(MemoryCollector::new(interval)));
+    collectors.push(Box::new(ProcessCollector::new(
+      cpu_memory.process_list.num_processes,
+      interval,
+    )));
+  }
+  if let Some(disk) = &config.disk {
+    collectors.push(Box::new(DiskCollector::new(disk)));
+  }
+  if let Some(gpu) = &config.gpu
+    && let Ok(nvml) = Nvml::init()
+  {
+    let interval = Duration::from_secs(gpu.update_interval);
+    collectors.push(Box::new(GpuCollector::new(Arc::new(nvml), interval)));
+  }
+  if let Some(network) = &config.network {
+    collectors.push(Box::new(NetworkCollector::new(network)));
+  }
+  collectors
+}
+
+fn collect_document(collectors: &mut [Box<dyn JsonCollector>]) -> Value {
+  let mut document = Map::new();
+  let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+  document.insert("timestamp".to_string(), timestamp.into());
+  for collector in collectors {
+    document.insert(collector.name().to_string(), collector.collect_json());
+  }
+  Value::Object(document)
+}
+
+/// Prints the samples of every collector in the config, without opening a window. Usage and speeds are rates, so the
+/// collectors sample once before the first document. Prints one document, or a line of *JSON* every `interval`.
+pub fn dump(config: &DogkyConfig, interval: Option<Duration>) -> Result<(), Box<dyn Error>> {
+  let mut collectors = get_collectors(config);
+  for collector in &mut collectors {
+    collector.collect_json();
+  }
+  let Some(interval) = interval else {
+    let longest_interval = collectors.iter().map(|collector| collector.interval()).max();
+    std::thread::sleep(longest_interval.unwrap_or_default());
+    println!("{}", serde_json::to_string_pretty(&collect_document(&mut collectors))?);
+    return Ok(());
+  };
+  let mut stdout = std::io::stdout().lock();
+  loop {
+    std::thread::sleep(interval);
+    writeln!(stdout, "{}", serde_json::to_string(&collect_document(&mut collectors))?)?;
+    stdout.flush()?;
+  }
+}
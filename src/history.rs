@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use circular_queue::CircularQueue;
+
+/// Samples further apart than this many intervals have a gap between them. Collectors drift a little.
+const MAX_INTERVALS_APART: f64 = 2.;
+
+/// Seconds since the epoch, and the value
+type Sample = (f64, f32);
+
+fn now() -> f64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64()
+}
+
+/// A graph's samples, timestamped so that downtime shows as a gap
+#[derive(Clone, PartialEq)]
+pub struct History {
+  /// Seconds between samples
+  interval: f64,
+  samples: CircularQueue<Sample>,
+}
+
+impl History {
+  pub fn new(capacity: usize, interval: Duration) -> Self {
+    History {
+      interval: interval.as_secs_f64(),
+      samples: CircularQueue::with_capacity(capacity),
+    }
+  }
+
+  pub fn push(&mut self, value: f32) {
+    self.samples.push((now(), value));
+  }
+
+  /// Newest first, with the number of intervals since the newest
+  pub fn iter_slots(&self) -> impl Iterator<Item = (usize, f32)> + '_ {
+    let mut slot = 0;
+    let mut newer_time = None;
+    self.samples.iter().map(move |&(time, value)| {
+      if let Some(newer_time) = newer_time.replace(time) {
+        let intervals = (newer_time - time) / self.interval;
+        slot += if intervals > MAX_INTERVALS_APART {
+          intervals.round() as usize
+        } else {
+          1
+        };
+      }
+      (slot, value)
+    })
+  }
+}
+
+/// Every graph's history, by name. Kept across restarts and reloads.
+#[derive(Clone, Default)]
+pub struct HistoryStore {
+  path: Option<PathBuf>,
+  /// Oldest first
+  histories: Arc<Mutex<HashMap<String, Vec<Sample>>>>,
+}
+
+impl HistoryStore {
+  /// Starts empty when there's no file yet
+  pub fn load(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+    let histories = match std::fs::read(&path) {
+      Ok(contents) => serde_json::from_slice(&contents)?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+      Err(err) => return Err(err.into()),
+    };
+    Ok(HistoryStore {
+      path: Some(path),
+      histories: Arc::new(Mutex::new(histories)),
+    })
+  }
+
+  /// Samples too old to fit in the graph are left out
+  pub fn get(&self, name: &str, capacity: usize, interval: Duration) -> History {
+    let mut history = History::new(capacity, interval);
+    let oldest = now() - capacity as f64 * history.interval;
+    if let Some(samples) = self.histories.lock().unwrap().get(name) {
+      for &sample in samples.iter().filter(|(time, _value)| *time >= oldest) {
+        history.samples.push(sample);
+      }
+    }
+    history
+  }
+
+  pub fn set(&self, name: &str, history: &History) {
+    let samples = history.samples.asc_iter().copied().collect();
+    self.histories.lock().unwrap().insert(name.to_string(), samples);
+  }
+
+  /// Replaces the file, so a crash midway doesn't lose it. Does nothing without a file.
+  pub fn save(&self) -> Result<(), Box<dyn Error>> {
+    let Some(path) = &self.path else {
+      return Ok(());
+    };
+    let contents = serde_json::to_vec(&*self.histories.lock().unwrap())?;
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, contents)?;
+    std::fs::rename(temp_path, path)?;
+    Ok(())
+  }
+}
@@ -1,83 +1,489 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::os::unix::net::UnixListener;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use clap::Parser;
 
 use freya::prelude::*;
+use futures_lite::StreamExt;
 use nvml_wrapper::Nvml;
 use styles_config::{GlobalStyles, StylesConfig};
-use velcro::vec;
 use winit::window::WindowLevel;
 
-use components::{
-  GpuComponent, cpu_memory_component, disk_component, machine_info_component, network_component, weather_component,
-};
-use custom_components::create_separator;
+use cli::{Cli, Command, ConfigFile};
+use collectors::MetricsBus;
+use components::{Panel, config_error_component};
+use config::{DogkyConfig, HeightMode, LayoutItem, WindowHeight};
+use config_file::ConfigError;
+use control::{Message, Reply};
+use history::HistoryStore;
+use metrics::Metrics;
+use move_window::{Placement, Repositioner, WindowManager};
+use path::ConfigPaths;
 
 mod api;
+mod cli;
+mod collectors;
 mod components;
 mod config;
+mod config_file;
+mod config_watcher;
+mod control;
+mod cpu_cores;
 mod custom_components;
+mod dump;
 mod format_size;
 mod freya_utils;
+mod history;
+mod metrics;
 mod move_window;
+mod open_metrics;
 mod path;
 mod serde_structs;
 mod styles_config;
 mod utils;
 
+/// Also saved on exit. A crash loses at most this much.
+const HISTORY_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
 struct DogkyApp {
+  paths: ConfigPaths,
+  placement: Arc<Mutex<Placement>>,
+  repositioner: Repositioner,
+  config: Option<DogkyConfig>,
   styles: StylesConfig,
+  errors: Vec<ConfigError>,
+  metrics: Metrics,
+  bus: MetricsBus,
+  history: HistoryStore,
+  /// Taken on the first render
+  listener: Mutex<Option<UnixListener>>,
+}
+
+fn print_errors(errors: &[ConfigError]) {
+  for error in errors {
+    eprintln!("{}", error);
+  }
+}
+
+/// Loads both config files. Styles that fail to load fall back to the defaults.
+fn load_configs(paths: &ConfigPaths) -> (Option<DogkyConfig>, StylesConfig, Vec<ConfigError>) {
+  let mut errors = vec![];
+  let config = config::load_config(paths).map_err(|err| errors.push(err)).ok();
+  let styles = styles_config::load_config(paths).unwrap_or_else(|err| {
+    errors.push(err);
+    StylesConfig::default()
+  });
+  (config, styles, errors)
+}
+
+fn get_placement(title: &'static str, config: Option<&DogkyConfig>, styles: &StylesConfig) -> Placement {
+  Placement {
+    window_manager: WindowManager::detect(),
+    title,
+    width: styles.width,
+    config: config.map(|config| config.window.clone()).unwrap_or_default(),
+    content_height: None,
+    hidden: false,
+  }
+}
+
+/// Reloads the config files into the app state. `State`s are handles, so a copy updates the same state.
+#[derive(Clone)]
+struct Reloader {
+  paths: ConfigPaths,
+  placement: Arc<Mutex<Placement>>,
+  repositioner: Repositioner,
+  config: State<Option<DogkyConfig>>,
+  styles: State<StylesConfig>,
+  errors: State<Vec<ConfigError>>,
+  generation: State<u64>,
+}
+
+impl Reloader {
+  /// Keeps the last config that loaded, and shows the errors.
+  async fn reload(&mut self) -> Vec<ConfigError> {
+    let paths = self.paths.clone();
+    // Includes can be anywhere, e.g. on a slow network share
+    let loaded = tokio::task::spawn_blocking(move || (config::load_config(&paths), styles_config::load_config(&paths)));
+    let (new_config, new_styles) = match loaded.await {
+      Ok(loaded) => loaded,
+      Err(err) => {
+        eprintln!("Failed to reload the config files: {}", err);
+        return vec![];
+      }
+    };
+    let mut new_errors = vec![];
+    match new_config {
+      Ok(new_config) => self.config.set(Some(new_config)),
+      Err(err) => new_errors.push(err),
+    }
+    match new_styles {
+      Ok(new_styles) => self.styles.set(new_styles),
+      Err(err) => new_errors.push(err),
+    }
+    // The width or the output may have changed
+    {
+      let mut placement = self.placement.lock().unwrap();
+      *placement = Placement {
+        window_manager: placement.window_manager,
+        content_height: placement.content_height,
+        hidden: placement.hidden,
+        ..get_placement(placement.title, self.config.read().as_ref(), &self.styles.read())
+      };
+    }
+    self.repositioner.update();
+    print_errors(&new_errors);
+    self.errors.set(new_errors.clone());
+    *self.generation.write() += 1;
+    new_errors
+  }
+}
+
+/// Hides the window, or lets the visibility rules show it, depending on whether it's hidden now.
+async fn set_hidden(reloader: &Reloader, hidden: impl FnOnce(bool) -> bool) -> Reply {
+  {
+    let mut placement = reloader.placement.lock().unwrap();
+    if placement.window_manager == WindowManager::Other {
+      return Reply::Error("Hiding the window needs Sway or i3".to_string());
+    }
+    placement.hidden = hidden(placement.hidden);
+  }
+  match reloader.repositioner.reposition_and_wait().await {
+    Ok(()) => Reply::Done,
+    Err(err) => Reply::Error(err),
+  }
+}
+
+/// Creates the panels in layout order, leaving out the ones that aren't available. Separators are only kept between
+/// panels. Panels are keyed on their refresh count, so a refresh restarts them.
+fn layout_children(config: &DogkyConfig, has_gpu: bool, refreshes: &HashMap<LayoutItem, u64>) -> Vec<Element> {
+  let mut items: Vec<LayoutItem> = vec![];
+  for &item in &config.layout {
+    let is_available = match item {
+      LayoutItem::Weather => config.weather.is_some(),
+      LayoutItem::CpuMemory => config.cpu_memory.is_some(),
+      LayoutItem::Disk => config.disk.is_some(),
+      LayoutItem::Gpu => config.gpu.is_some() && has_gpu,
+      LayoutItem::Network => config.network.is_some(),
+      LayoutItem::MachineInfo | LayoutItem::Separator => true,
+    };
+    let is_separator_after_nothing =
+      item == LayoutItem::Separator && items.last().is_none_or(|&last| last == LayoutItem::Separator);
+    if is_available && !is_separator_after_nothing {
+      items.push(item);
+    }
+  }
+  if items.last() == Some(&LayoutItem::Separator) {
+    items.pop();
+  }
+  items
+    .into_iter()
+    .enumerate()
+    .map(|(i, item)| {
+      let refresh_count = refreshes.get(&item).copied().unwrap_or_default();
+      rect()
+        .key((i, item, refresh_count))
+        .width(Size::percent(100.))
+        .child(Panel(item))
+        .into()
+    })
+    .collect()
 }
 
 impl App for DogkyApp {
   fn render(&self) -> impl IntoElement {
+    // The last config that loaded, if any
+    let config = use_state(|| self.config.clone());
+    let styles = use_state(|| self.styles.clone());
+    let errors = use_state(|| self.errors.clone());
+    // Bumped on every reload. The panels are keyed on it, so they restart with the new config.
+    let generation = use_state(|| 0u64);
+    // Bumped by `dogky msg refresh`
+    let mut refreshes = use_state(HashMap::<LayoutItem, u64>::new);
+    let nvml = use_hook(|| Nvml::init().ok().map(Arc::new));
+
+    let reloader = Reloader {
+      paths: self.paths.clone(),
+      placement: Arc::clone(&self.placement),
+      repositioner: self.repositioner.clone(),
+      config,
+      styles,
+      errors,
+      generation,
+    };
+    use_hook(|| {
+      let mut reloader = reloader.clone();
+      spawn(async move {
+        let mut changes = match config_watcher::watch_config_files(&reloader.paths) {
+          Ok(changes) => changes,
+          Err(err) => {
+            eprintln!("Not watching the config files: {}", err);
+            return;
+          }
+        };
+        while changes.next().await.is_some() {
+          reloader.reload().await;
+        }
+      })
+    });
+    use_hook(|| {
+      let Some(listener) = self.listener.lock().unwrap().take() else {
+        return;
+      };
+      let mut reloader = reloader.clone();
+      let metrics = self.metrics.clone();
+      spawn(async move {
+        let handle = async move |message: Message| match message {
+          Message::Show => set_hidden(&reloader, |_| false).await,
+          Message::Hide => set_hidden(&reloader, |_| true).await,
+          Message::Toggle => set_hidden(&reloader, |hidden| !hidden).await,
+          Message::Reload => {
+            let errors = reloader.reload().await;
+            if errors.is_empty() {
+              Reply::Done
+            } else {
+              Reply::Error(errors.iter().map(ConfigError::to_string).collect::<Vec<_>>().join("\n"))
+            }
+          }
+          Message::Refresh { panel } => {
+            *refreshes.write().entry(panel).or_default() += 1;
+            Reply::Done
+          }
+          Message::Metrics => Reply::Metrics(metrics.to_json()),
+        };
+        if let Err(err) = control::serve(listener, handle).await {
+          eprintln!("Stopped listening for messages: {}", err);
+        }
+      });
+    });
+
+    let config = config.read();
+    let styles = styles.read();
+    let errors = errors.read();
+    let refresh_counts = refreshes.read();
+
     let global_styles = GlobalStyles {
-      container_width: self.styles.width as f32 - self.styles.padding.left() - self.styles.padding.right(),
-      h_gap: self.styles.h_gap,
+      container_width: styles.width as f32 - styles.padding.left() - styles.padding.right(),
+      h_gap: styles.h_gap,
+      separator_height: styles.separator_height,
+      error_color: styles.error_color.clone(),
     };
     provide_context(global_styles);
-    provide_context(self.styles.weather.clone());
-    provide_context(self.styles.machine_info.clone());
-    provide_context(self.styles.cpu_memory.clone());
-    provide_context(self.styles.disk.clone());
-    provide_context(self.styles.gpu.clone());
-    provide_context(self.styles.network.clone());
-
-    let config = config::load_config().unwrap();
-    provide_context(config.weather);
-    provide_context(config.cpu_memory);
-    provide_context(config.disk);
-    provide_context(config.gpu);
-    provide_context(config.network);
-
-    let separator = create_separator(self.styles.separator_height);
+    provide_context(styles.weather.clone());
+    provide_context(styles.machine_info.clone());
+    provide_context(styles.cpu_memory.clone());
+    provide_context(styles.disk.clone());
+    provide_context(styles.gpu.clone());
+    provide_context(styles.network.clone());
+    provide_context(self.metrics.clone());
+    provide_context(self.bus.clone());
+    provide_context(self.history.clone());
+    provide_context(refreshes);
+
+    if let Some(config) = &*config {
+      if let Some(weather) = &config.weather {
+        provide_context(weather.clone());
+      }
+      if let Some(cpu_memory) = &config.cpu_memory {
+        provide_context(cpu_memory.clone());
+      }
+      if let Some(disk) = &config.disk {
+        provide_context(disk.clone());
+      }
+      if let Some(gpu) = &config.gpu {
+        provide_context(gpu.clone());
+      }
+      if let Some(network) = &config.network {
+        provide_context(network.clone());
+      }
+    }
+    if let Some(nvml) = &nvml {
+      provide_context(Arc::clone(nvml));
+    }
+
+    // Measures the panels, for `height: fit`
+    let on_content_sized = {
+      let shared_placement = Arc::clone(&self.placement);
+      let repositioner = self.repositioner.clone();
+      let padding_height = styles.padding.top() + styles.padding.bottom();
+      move |event: Event<SizedEventData>| {
+        let height = (event.area.height() + padding_height).ceil() as u32;
+        {
+          let mut placement = shared_placement.lock().unwrap();
+          if placement.content_height == Some(height) {
+            return;
+          }
+          placement.content_height = Some(height);
+          if placement.config.height != WindowHeight::Mode(HeightMode::Fit) {
+            return;
+          }
+        }
+        // Placing it is left to the placement thread, as it waits on the window manager
+        repositioner.reposition();
+      }
+    };
 
     rect()
       .width(Size::percent(100.))
       .height(Size::percent(100.))
-      .background(*self.styles.background_color)
-      .color(*self.styles.text_color)
-      .font_size(self.styles.text_size)
-      .padding(*self.styles.padding)
-      .children(vec![
-        weather_component().into(),
-        separator.clone().into(),
-        machine_info_component().into(),
-        separator.clone().into(),
-        cpu_memory_component().into(),
-        separator.clone().into(),
-        disk_component().into(),
-        ..Nvml::init().map_or(vec![], |nvml| {
-          vec![separator.clone().into(), GpuComponent { nvml: Arc::from(nvml) }.into()]
-        }),
-        separator.clone().into(),
-        network_component(),
-      ])
+      .background(*styles.background_color)
+      .color(*styles.text_color)
+      .font_size(styles.text_size)
+      .padding(*styles.padding)
+      .child(
+        rect()
+          .width(Size::percent(100.))
+          .on_sized(on_content_sized)
+          .child(config_error_component(&errors, *styles.error_color))
+          .child(rect().key(generation()).width(Size::percent(100.)).children(
+            config.as_ref().map_or(vec![], |config| {
+              layout_children(config, nvml.is_some(), &refresh_counts)
+            }),
+          )),
+      )
   }
 }
 
-fn main() {
-  let styles = styles_config::load_config().unwrap();
+fn main() -> ExitCode {
+  let cli = Cli::parse();
+  let paths = match ConfigPaths::new(cli.config, cli.styles, cli.profile.as_deref()) {
+    Ok(paths) => paths,
+    Err(err) => {
+      eprintln!("Failed to find the config files: {}", err);
+      return ExitCode::FAILURE;
+    }
+  };
+  match cli.command {
+    Some(Command::CheckConfig) => {
+      let (_config, _styles, errors) = load_configs(&paths);
+      print_errors(&errors);
+      return if errors.is_empty() {
+        println!("Config is valid");
+        ExitCode::SUCCESS
+      } else {
+        ExitCode::FAILURE
+      };
+    }
+    Some(Command::PrintDefaultConfig { file }) => {
+      print!(
+        "{}",
+        match file {
+          ConfigFile::Config => cli::DEFAULT_CONFIG,
+          ConfigFile::Styles => cli::DEFAULT_STYLES,
+        }
+      );
+      return ExitCode::SUCCESS;
+    }
+    Some(Command::Schema { file }) => {
+      let schema = match file {
+        ConfigFile::Config => schemars::schema_for!(DogkyConfig),
+        ConfigFile::Styles => schemars::schema_for!(StylesConfig),
+      };
+      println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+      return ExitCode::SUCCESS;
+    }
+    Some(Command::Dump { interval }) => {
+      let config = match config::load_config(&paths) {
+        Ok(config) => config,
+        Err(err) => {
+          print_errors(&[err]);
+          return ExitCode::FAILURE;
+        }
+      };
+      if let Err(err) = dump::dump(&config, interval.map(Duration::from_secs)) {
+        eprintln!("Failed to dump the metrics: {}", err);
+        return ExitCode::FAILURE;
+      }
+      return ExitCode::SUCCESS;
+    }
+    Some(Command::Msg { message }) => {
+      let reply = path::get_socket_path(cli.profile.as_deref())
+        .map_err(|err| err.into())
+        .and_then(|socket_path| control::send(&socket_path, &message));
+      return match reply {
+        Ok(Reply::Done) => ExitCode::SUCCESS,
+        Ok(Reply::Metrics(metrics)) => {
+          println!("{}", serde_json::to_string_pretty(&metrics).unwrap());
+          ExitCode::SUCCESS
+        }
+        Ok(Reply::Error(err)) => {
+          eprintln!("{}", err);
+          ExitCode::FAILURE
+        }
+        Err(err) => {
+          eprintln!("Failed to send the message: {}", err);
+          ExitCode::FAILURE
+        }
+      };
+    }
+    None => {}
+  }
+
+  // One instance per profile. Launching another shows the running one instead.
+  let socket_path = path::get_socket_path(cli.profile.as_deref())
+    .inspect_err(|err| eprintln!("Not listening for messages: {}", err))
+    .ok();
+  let listener = match socket_path.as_deref().map(control::bind) {
+    Some(Ok(Some(listener))) => Some(listener),
+    Some(Ok(None)) => {
+      eprintln!("Already running, showing it instead");
+      if let Err(err) = control::send(socket_path.as_deref().unwrap(), &Message::Show) {
+        eprintln!("Failed to send the message: {}", err);
+        return ExitCode::FAILURE;
+      }
+      return ExitCode::SUCCESS;
+    }
+    Some(Err(err)) => {
+      eprintln!("Not listening for messages: {}", err);
+      None
+    }
+    None => None,
+  };
+  let is_listening = listener.is_some();
+
+  let (config, styles, errors) = load_configs(&paths);
+  print_errors(&errors);
+  // Leaked, as *Freya* needs a static title. It's only created once.
+  let title: &'static str = match &cli.profile {
+    Some(profile) => format!("Dogky {}", profile).leak(),
+    None => "Dogky",
+  };
+  let placement = get_placement(title, config.as_ref(), &styles);
+  let shared_placement = Arc::new(Mutex::new(placement.clone()));
+  let repositioner = Repositioner::spawn(Arc::clone(&shared_placement));
   let font = styles.font.clone();
-  let width = styles.width;
+  let metrics = Metrics::default();
+  let history = path::get_history_path(cli.profile.as_deref())
+    .map_err(|err| err.into())
+    .and_then(HistoryStore::load)
+    .unwrap_or_else(|err| {
+      eprintln!("Not keeping the graph history: {}", err);
+      HistoryStore::default()
+    });
+  {
+    let history = history.clone();
+    std::thread::spawn(move || {
+      loop {
+        std::thread::sleep(HISTORY_SAVE_INTERVAL);
+        if let Err(err) = history.save() {
+          eprintln!("Failed to save the graph history: {}", err);
+        }
+      }
+    });
+  }
+  if let Some(exporter) = config.as_ref().and_then(|config| config.exporter.as_ref()) {
+    match TcpListener::bind(&exporter.listen) {
+      Ok(listener) => {
+        let metrics = metrics.clone();
+        std::thread::spawn(move || open_metrics::serve(listener, metrics));
+      }
+      Err(err) => eprintln!("Not exporting the metrics on {}: {}", exporter.listen, err),
+    }
+  }
 
   // Build a *Tokio* runtime manually, to not interfere with *Freya*. Otherwise it hangs eventually.
   let rt = tokio::runtime::Builder::new_multi_thread()
@@ -89,15 +495,37 @@ fn main() {
 
   launch(
     LaunchConfig::new().with_default_font(font).with_window(
-      WindowConfig::new_app(DogkyApp { styles })
-        .with_window_handle(move |_window| move_window::move_window(width).unwrap())
-        .with_app_id("dogky")
-        .with_title("Dogky")
-        .with_resizable(false)
-        .with_decorations(false)
-        .with_transparency(true)
-        .with_background(Color::TRANSPARENT)
-        .with_window_attributes(|attributes, _| attributes.with_window_level(WindowLevel::AlwaysOnBottom)),
+      WindowConfig::new_app(DogkyApp {
+        paths,
+        placement: shared_placement,
+        repositioner,
+        config,
+        styles,
+        errors,
+        bus: MetricsBus::new(metrics.clone()),
+        metrics,
+        history: history.clone(),
+        listener: Mutex::new(listener),
+      })
+      .with_window_handle(move |_window| {
+        if let Err(err) = move_window::move_window(&placement) {
+          eprintln!("Failed to place the window: {}", err);
+        }
+      })
+      .with_app_id("dogky")
+      .with_title(title)
+      .with_resizable(false)
+      .with_decorations(false)
+      .with_transparency(true)
+      .with_background(Color::TRANSPARENT)
+      .with_window_attributes(|attributes, _| attributes.with_window_level(WindowLevel::AlwaysOnBottom)),
     ),
   );
+  if let Err(err) = history.save() {
+    eprintln!("Failed to save the graph history: {}", err);
+  }
+  if is_listening && let Some(socket_path) = socket_path {
+    let _ = std::fs::remove_file(socket_path);
+  }
+  ExitCode::SUCCESS
 }
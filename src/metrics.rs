@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::open_metrics::{OpenMetrics, OpenMetricsWriter};
+
+struct Sample {
+  json: Value,
+  open_metrics: String,
+}
+
+/// The latest sample of each collector, by collector name. Shared with the control socket and the exporter.
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<BTreeMap<String, Sample>>>);
+
+impl Metrics {
+  pub fn set(&self, name: &str, sample: &(impl Serialize + OpenMetrics)) {
+    let json = match serde_json::to_value(sample) {
+      Ok(json) => json,
+      Err(err) => {
+        eprintln!("Failed to serialize the {} sample: {}", name, err);
+        return;
+      }
+    };
+    let mut writer = OpenMetricsWriter::new(name);
+    sample.write_open_metrics(&mut writer);
+    let sample = Sample {
+      json,
+      open_metrics: writer.into_text(),
+    };
+    self.0.lock().unwrap().insert(name.to_string(), sample);
+  }
+
+  pub fn remove(&self, name: &str) {
+    self.0.lock().unwrap().remove(name);
+  }
+
+  pub fn to_json(&self) -> Value {
+    let samples = self.0.lock().unwrap();
+    Value::Object(
+      samples
+        .iter()
+        .map(|(name, sample)| (name.clone(), sample.json.clone()))
+        .collect(),
+    )
+  }
+
+  /// A whole exposition, ending with `# EOF`
+  pub fn to_open_metrics(&self) -> String {
+    let samples = self.0.lock().unwrap();
+    let mut text: String = samples.values().map(|sample| sample.open_metrics.as_str()).collect();
+    text.push_str("# EOF\n");
+    text
+  }
+}
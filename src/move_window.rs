@@ -1,45 +1,450 @@
-use swayipc::{Connection, Rect};
+use std::sync::{Arc, Mutex, mpsc};
 
-fn get_workspace_rect() -> Result<Rect, Box<dyn std::error::Error>> {
-  let mut connection = Connection::new()?;
-  let workspaces = connection.get_workspaces()?;
-  let workspace = workspaces
+use swayipc::{Connection, Event, EventType, Node, NodeType, Rect, WindowChange, Workspace, WorkspaceChange};
+use tokio::sync::oneshot;
+
+use crate::config::{
+  HeightMode, HorizontalAnchor, OutputSelector, VerticalAnchor, WindowHeight, WindowPlacementConfig, WorkspaceSelector,
+};
+
+const APP_ID: &str = "dogky";
+/// The pseudo output holding the scratchpad, in both *Sway* and *i3*
+const SCRATCHPAD_OUTPUT: &str = "__i3";
+
+/// The window manager the window is placed through, over IPC.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WindowManager {
+  Sway,
+  /// On *X11*
+  I3,
+  /// Placement is left to it, e.g. its window rules
+  Other,
+}
+
+impl WindowManager {
+  /// *Sway* and *i3* set their socket path for the processes they start
+  pub fn detect() -> WindowManager {
+    if std::env::var_os("SWAYSOCK").is_some() {
+      WindowManager::Sway
+    } else if std::env::var_os("I3SOCK").is_some() {
+      WindowManager::I3
+    } else {
+      WindowManager::Other
+    }
+  }
+}
+
+/// What's needed to place the window. Placement is skipped under other window managers than *Sway* and *i3*.
+#[derive(Clone)]
+pub struct Placement {
+  /// Detected at startup
+  pub window_manager: WindowManager,
+  /// Instances share the app ID, so the title tells them apart
+  pub title: &'static str,
+  pub width: u32,
+  pub config: WindowPlacementConfig,
+  /// Measured, for `height: fit`
+  pub content_height: Option<u32>,
+  /// Hidden on request, whatever the visibility rules say
+  pub hidden: bool,
+}
+
+impl Placement {
+  /// On *X11*, the app ID is the window class
+  fn criteria(&self) -> String {
+    let app_id_criterion = match self.window_manager {
+      WindowManager::I3 => "class",
+      _ => "app_id",
+    };
+    format!(
+      "[{}=\"{}\" title=\"^{}$\"]",
+      app_id_criterion,
+      APP_ID,
+      regex::escape(self.title)
+    )
+  }
+
+  fn is_window(&self, node: &Node) -> bool {
+    let class = node
+      .window_properties
+      .as_ref()
+      .and_then(|properties| properties.class.as_deref());
+    (node.app_id.as_deref() == Some(APP_ID) || class == Some(APP_ID)) && node.name.as_deref() == Some(self.title)
+  }
+}
+
+/// The parts of an output placement needs, from *Sway* or *i3*.
+struct OutputInfo {
+  name: String,
+  /// Make, model and serial. *i3* doesn't report them.
+  description: Option<[String; 3]>,
+  active: bool,
+  focused: bool,
+  current_workspace: Option<String>,
+}
+
+/// *i3*'s outputs lack the fields *swayipc* requires, so they're read from the visible workspaces instead. Every active
+/// output shows one.
+fn get_outputs(connection: &mut Connection, window_manager: WindowManager) -> Result<Vec<OutputInfo>, swayipc::Error> {
+  if window_manager == WindowManager::I3 {
+    return Ok(
+      connection
+        .get_workspaces()?
+        .into_iter()
+        .filter(|workspace| workspace.visible)
+        .map(|workspace| OutputInfo {
+          name: workspace.output,
+          description: None,
+          active: true,
+          focused: workspace.focused,
+          current_workspace: Some(workspace.name),
+        })
+        .collect(),
+    );
+  }
+  Ok(
+    connection
+      .get_outputs()?
+      .into_iter()
+      .map(|output| OutputInfo {
+        name: output.name,
+        description: Some([output.make, output.model, output.serial]),
+        active: output.active,
+        focused: output.focused,
+        current_workspace: output.current_workspace,
+      })
+      .collect(),
+  )
+}
+
+fn is_match(selector: &OutputSelector, output: &OutputInfo) -> bool {
+  match (selector, &output.description) {
+    (OutputSelector::Name(name), _) => output.name == *name,
+    (OutputSelector::Description { make, model, serial }, Some(description)) => [make, model, serial]
+      .iter()
+      .zip(description)
+      .all(|(regex, value)| regex.as_ref().is_none_or(|regex| regex.is_match(value))),
+    (OutputSelector::Description { .. }, None) => false,
+  }
+}
+
+/// The name of the output the window is on, if it's open. `__i3` when it's hidden.
+fn find_window_output(tree: &Node, placement: &Placement) -> Option<String> {
+  tree
+    .nodes
     .iter()
-    .find(|workspace| workspace.focused)
-    .ok_or("No focused workspace")?;
-  Ok(workspace.rect)
+    .find(|output| output.find_as_ref(|node| placement.is_window(node)).is_some())
+    .and_then(|output| output.name.clone())
 }
 
-const WINDOW_CRITERIA: &str = "[app_id=\"dogky\"]";
+/// The selected output. Otherwise, the one the window is on, so it stays put. Otherwise, the focused one.
+fn find_output<'a>(
+  outputs: &'a [OutputInfo],
+  selector: Option<&OutputSelector>,
+  window_output: Option<&str>,
+) -> Option<&'a OutputInfo> {
+  let active_outputs = || outputs.iter().filter(|output| output.active);
+  selector
+    .and_then(|selector| active_outputs().find(|output| is_match(selector, output)))
+    .or_else(|| active_outputs().find(|output| Some(output.name.as_str()) == window_output))
+    .or_else(|| outputs.iter().find(|output| output.focused))
+}
 
-pub fn move_window(window_width: u32) -> Result<(), Box<dyn std::error::Error>> {
-  let mut connection = Connection::new()?;
-  let outputs = connection.get_outputs()?;
-  let output = outputs.iter().find(|output| output.focused).ok_or("No output")?;
-  let current_mode = output.current_mode.ok_or("No current mode")?;
-  let scale = output.scale.ok_or("No scale")?;
-  let [output_width, output_height] = [
-    (current_mode.width as f64 / scale).round() as i32,
-    (current_mode.height as f64 / scale).round() as i32,
+/// The workspace shown on the output the window goes on.
+fn find_workspace(
+  connection: &mut Connection,
+  placement: &Placement,
+  window_output: Option<&str>,
+) -> Result<Workspace, Box<dyn std::error::Error>> {
+  let outputs = get_outputs(connection, placement.window_manager)?;
+  let output = find_output(&outputs, placement.config.output.as_ref(), window_output).ok_or("No output")?;
+  let workspace_name = output.current_workspace.as_ref().ok_or("No workspace on the output")?;
+  connection
+    .get_workspaces()?
+    .into_iter()
+    .find(|workspace| workspace.name == *workspace_name)
+    .ok_or_else(|| "No workspace on the output".into())
+}
+
+/// Whether the visibility rules allow the window on the workspace.
+fn is_visible(tree: &Node, placement: &Placement, workspace: &Workspace) -> bool {
+  if placement.hidden {
+    return false;
+  }
+  let visibility = &placement.config.visibility;
+  let is_listed = visibility.workspaces.as_ref().is_none_or(|selectors| {
+    selectors.iter().any(|selector| match selector {
+      WorkspaceSelector::Number(num) => workspace.num == *num,
+      WorkspaceSelector::Name(name) => workspace.name == *name,
+    })
+  });
+  if !is_listed {
+    return false;
+  }
+  if !visibility.hide_on_fullscreen {
+    return true;
+  }
+  let is_fullscreen = |mode: u8| move |node: &Node| node.fullscreen_mode == Some(mode) && !placement.is_window(node);
+  let workspace_node = tree
+    .find_as_ref(|node| node.node_type == NodeType::Workspace && node.name.as_deref() == Some(workspace.name.as_str()));
+  let has_workspace_fullscreen = workspace_node.is_some_and(|node| node.find_as_ref(is_fullscreen(1)).is_some());
+  // Global fullscreen covers every output
+  let has_global_fullscreen = tree.find_as_ref(is_fullscreen(2)).is_some();
+  !has_workspace_fullscreen && !has_global_fullscreen
+}
+
+/// *Sway* or *i3* commands to size and position the window, without criteria. `workspace_rect` excludes the bars,
+/// *i3bar*'s dock area included. It's in global coordinates, like the position.
+fn get_placement_commands(placement: &Placement, workspace_rect: Rect) -> Vec<String> {
+  let config = &placement.config;
+  let [margin_top, margin_right, margin_bottom, margin_left] = [
+    config.margin.top(),
+    config.margin.right(),
+    config.margin.bottom(),
+    config.margin.left(),
+  ]
+  .map(|margin| margin.round() as i32);
+  let width = placement.width as i32;
+  let max_height = workspace_rect.height - margin_top - margin_bottom;
+  let height = match config.height {
+    WindowHeight::Fixed(height) => height as i32,
+    WindowHeight::Mode(HeightMode::Fit) => placement.content_height.map_or(max_height, |height| height as i32),
+    WindowHeight::Mode(HeightMode::Fill) => max_height,
+  }
+  .min(max_height);
+
+  let pos_x = match config.horizontal_anchor {
+    HorizontalAnchor::Left => workspace_rect.x + margin_left,
+    HorizontalAnchor::Right => workspace_rect.x + workspace_rect.width - width - margin_right,
+  };
+  let pos_y = match config.vertical_anchor {
+    VerticalAnchor::Top => workspace_rect.y + margin_top,
+    VerticalAnchor::Bottom => workspace_rect.y + workspace_rect.height - height - margin_bottom,
+  };
+
+  let mut commands = vec![
+    format!("resize set {} px {} px", width, height),
+    format!("move absolute position {} px {} px", pos_x, pos_y),
   ];
-  let workspace_rect = get_workspace_rect()?;
-  let bars_height = output_height - workspace_rect.height; // Possibly 0
-
-  let inner_height = output_height - bars_height;
-  let [pos_x, pos_y] = [output_width - window_width as i32, bars_height];
-
-  connection.run_command(
-    [
-      format!(
-        "for_window {} resize set {} {}",
-        WINDOW_CRITERIA, window_width, inner_height
-      ),
-      format!(
-        "for_window {} move absolute position {} {}",
-        WINDOW_CRITERIA, pos_x, pos_y
-      ),
-    ]
-    .join(";"),
-  )?;
+  // *Sway* floats fixed size windows by itself
+  if placement.window_manager == WindowManager::I3 {
+    commands.insert(0, "floating enable".to_string());
+  }
+  commands
+}
+
+/// The rules placing the window when it appears, as one command.
+fn get_rules_command(connection: &mut Connection, placement: &Placement) -> Result<String, Box<dyn std::error::Error>> {
+  let workspace = find_workspace(connection, placement, None)?;
+  Ok(
+    get_placement_commands(placement, workspace.rect)
+      .iter()
+      .map(|command| format!("for_window {} {}", placement.criteria(), command))
+      .collect::<Vec<_>>()
+      .join(";"),
+  )
+}
+
+/// Adds rules to place the window when it appears.
+pub fn move_window(placement: &Placement) -> Result<(), Box<dyn std::error::Error>> {
+  if placement.window_manager == WindowManager::Other {
+    return Ok(());
+  }
+  let mut connection = Connection::new()?;
+  let rules = get_rules_command(&mut connection, placement)?;
+  connection.run_command(rules)?;
   Ok(())
 }
+
+/// What the window was last made to be.
+#[derive(Clone, PartialEq)]
+enum Target {
+  /// In the scratchpad
+  Hidden,
+  /// Placed with the commands
+  Shown(Vec<String>),
+}
+
+/// Keeps what was last applied, to only send commands when it changes.
+#[derive(Default)]
+struct Repositioning {
+  target: Option<Target>,
+  /// The last `for_window` rules added, besides the ones from startup
+  rules: Option<String>,
+}
+
+impl Repositioning {
+  /// Places the window again, e.g. after its width changed. Also hides it in the scratchpad, or shows it, following
+  /// the visibility rules. Skipped while it's not open, or already where it should be.
+  fn reposition(&mut self, placement: &Placement) -> Result<(), Box<dyn std::error::Error>> {
+    let mut connection = Connection::new()?;
+    let tree = connection.get_tree()?;
+    let Some(window_output) = find_window_output(&tree, placement) else {
+      self.target = None;
+      return Ok(());
+    };
+    let is_hidden = window_output == SCRATCHPAD_OUTPUT;
+    let workspace = find_workspace(&mut connection, placement, Some(&window_output))?;
+    let target = if is_visible(&tree, placement, &workspace) {
+      Target::Shown(get_placement_commands(placement, workspace.rect))
+    } else {
+      Target::Hidden
+    };
+    if self.target.as_ref() == Some(&target) && is_hidden == (target == Target::Hidden) {
+      return Ok(());
+    }
+    let commands = match &target {
+      // Shows it on the focused workspace. Moving it then takes it to the right output.
+      Target::Shown(commands) if is_hidden => [vec!["scratchpad show".to_string()], commands.clone()].concat(),
+      Target::Shown(commands) => commands.clone(),
+      Target::Hidden if is_hidden => vec![],
+      Target::Hidden => vec!["move scratchpad".to_string()],
+    };
+    self.target = None;
+    if !commands.is_empty() {
+      connection.run_command(
+        commands
+          .iter()
+          .map(|command| format!("{} {}", placement.criteria(), command))
+          .collect::<Vec<_>>()
+          .join(";"),
+      )?;
+    }
+    self.target = Some(target);
+    Ok(())
+  }
+
+  /// Rules can't be removed, but the last one added wins, so adding new ones replaces the old ones.
+  fn update_rules(&mut self, placement: &Placement) -> Result<(), Box<dyn std::error::Error>> {
+    let mut connection = Connection::new()?;
+    let rules = get_rules_command(&mut connection, placement)?;
+    if self.rules.as_ref() != Some(&rules) {
+      connection.run_command(&rules)?;
+      self.rules = Some(rules);
+    }
+    Ok(())
+  }
+}
+
+enum Request {
+  /// From the window manager
+  Event(Event),
+  /// After the placement changed. `update_rules` also replaces the rules placing the window when it appears.
+  Reposition {
+    update_rules: bool,
+    reply: Option<oneshot::Sender<Result<(), String>>>,
+  },
+}
+
+/// Whether the event can change where the window goes.
+fn is_relevant(event: &Event, placement: &Placement) -> bool {
+  match event {
+    Event::Window(event) => match event.change {
+      // Applies the visibility rules once it appears
+      WindowChange::New => true,
+      // Skips the window's own moves, from placing it
+      WindowChange::Close | WindowChange::FullscreenMode | WindowChange::Move => !placement.is_window(&event.container),
+      _ => false,
+    },
+    // Only the workspace rules depend on which workspace is shown
+    Event::Workspace(event) => {
+      event.change != WorkspaceChange::Focus || placement.config.visibility.workspaces.is_some()
+    }
+    _ => true,
+  }
+}
+
+fn forward_events(sender: mpsc::Sender<Request>) -> Result<(), Box<dyn std::error::Error>> {
+  let events = Connection::new()?.subscribe([EventType::Output, EventType::Workspace, EventType::Window])?;
+  for event in events {
+    if sender.send(Request::Event(event?)).is_err() {
+      break;
+    }
+  }
+  Ok(())
+}
+
+fn reposition_on_requests(placement: Arc<Mutex<Placement>>, requests: mpsc::Receiver<Request>) {
+  let mut repositioning = Repositioning::default();
+  for request in requests {
+    let placement = placement.lock().unwrap().clone();
+    match request {
+      Request::Event(event) => {
+        if !is_relevant(&event, &placement) {
+          continue;
+        }
+        if let Err(err) = repositioning.reposition(&placement) {
+          eprintln!("Failed to reposition the window: {}", err);
+        }
+      }
+      Request::Reposition { update_rules, reply } => {
+        let mut result = repositioning.reposition(&placement);
+        if update_rules && result.is_ok() {
+          result = repositioning.update_rules(&placement);
+        }
+        let result = result.map_err(|err| err.to_string());
+        match reply {
+          Some(reply) => {
+            let _ = reply.send(result);
+          }
+          None => {
+            if let Err(err) = result {
+              eprintln!("Failed to reposition the window: {}", err);
+            }
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Asks the placement thread to place the window again. Does nothing under other window managers.
+#[derive(Clone)]
+pub struct Repositioner(mpsc::Sender<Request>);
+
+impl Repositioner {
+  /// Places the window again whenever an output is added, removed or changed, the workspaces change, or a window goes
+  /// fullscreen, opens, closes or moves, and when asked to. `placement` can be updated meanwhile.
+  pub fn spawn(placement: Arc<Mutex<Placement>>) -> Repositioner {
+    let (sender, requests) = mpsc::channel();
+    if placement.lock().unwrap().window_manager != WindowManager::Other {
+      let events = sender.clone();
+      std::thread::spawn(move || {
+        if let Err(err) = forward_events(events) {
+          eprintln!("Not repositioning on window manager events: {}", err);
+        }
+      });
+      std::thread::spawn(move || reposition_on_requests(placement, requests));
+    }
+    Repositioner(sender)
+  }
+
+  /// After the placement changed, without waiting.
+  pub fn reposition(&self) {
+    let _ = self.0.send(Request::Reposition {
+      update_rules: false,
+      reply: None,
+    });
+  }
+
+  /// After the placement changed, e.g. on a reload. Also replaces the rules placing the window when it appears.
+  pub fn update(&self) {
+    let _ = self.0.send(Request::Reposition {
+      update_rules: true,
+      reply: None,
+    });
+  }
+
+  /// Waits for the window to be placed.
+  pub async fn reposition_and_wait(&self) -> Result<(), String> {
+    let (reply, result) = oneshot::channel();
+    let request = Request::Reposition {
+      update_rules: false,
+      reply: Some(reply),
+    };
+    if self.0.send(request).is_err() {
+      return Ok(());
+    }
+    result.await.unwrap_or(Ok(()))
+  }
+}
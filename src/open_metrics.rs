@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use crate::metrics::Metrics;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A sample that can be exposed in the OpenMetrics text format
+pub trait OpenMetrics {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter);
+}
+
+impl<T: OpenMetrics> OpenMetrics for Option<T> {
+  fn write_open_metrics(&self, writer: &mut OpenMetricsWriter) {
+    if let Some(sample) = self {
+      sample.write_open_metrics(writer);
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+pub enum MetricType {
+  Gauge,
+  Counter,
+}
+
+/// Writes metric families named `dogky_<collector name>_<name>_<unit>`
+pub struct OpenMetricsWriter {
+  prefix: String,
+  text: String,
+  family: (MetricType, String),
+}
+
+impl OpenMetricsWriter {
+  pub fn new(collector_name: &str) -> Self {
+    OpenMetricsWriter {
+      prefix: format!("dogky_{collector_name}"),
+      text: String::new(),
+      family: (MetricType::Gauge, String::new()),
+    }
+  }
+
+  /// Starts a metric family, whose samples follow
+  pub fn family(&mut self, metric_type: MetricType, name: &str, unit: Option<&str>, help: &str) -> &mut Self {
+    let name = match unit {
+      Some(unit) => format!("{}_{name}_{unit}", self.prefix),
+      None => format!("{}_{name}", self.prefix),
+    };
+    let type_name = match metric_type {
+      MetricType::Gauge => "gauge",
+      MetricType::Counter => "counter",
+    };
+    writeln!(self.text, "# TYPE {name} {type_name}").unwrap();
+    if let Some(unit) = unit {
+      writeln!(self.text, "# UNIT {name} {unit}").unwrap();
+    }
+    writeln!(self.text, "# HELP {name} {help}").unwrap();
+    self.family = (metric_type, name);
+    self
+  }
+
+  pub fn sample(&mut self, labels: &[(&str, &str)], value: f64) -> &mut Self {
+    let (metric_type, name) = &self.family;
+    self.text.push_str(name);
+    if let MetricType::Counter = metric_type {
+      self.text.push_str("_total");
+    }
+    if !labels.is_empty() {
+      let labels = labels
+        .iter()
+        .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+      write!(self.text, "{{{labels}}}").unwrap();
+    }
+    let value = match value {
+      f64::INFINITY => "+Inf".to_string(),
+      f64::NEG_INFINITY => "-Inf".to_string(),
+      value => value.to_string(),
+    };
+    writeln!(self.text, " {value}").unwrap();
+    self
+  }
+
+  pub fn into_text(self) -> String {
+    self.text
+  }
+}
+
+fn escape_label_value(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn handle_connection(stream: TcpStream, metrics: &Metrics) -> Result<(), Box<dyn Error>> {
+  stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+  stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+  let mut reader = BufReader::new(&stream);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+  // Skip the headers, there's no body to a GET
+  let mut header = String::new();
+  while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+    header.clear();
+  }
+
+  let mut parts = request_line.split_whitespace();
+  let (status, content_type, body) = match (parts.next(), parts.next()) {
+    (Some("GET"), Some("/metrics")) => ("200 OK", CONTENT_TYPE, metrics.to_open_metrics()),
+    (Some("GET"), _) => (
+      "404 Not Found",
+      "text/plain",
+      "Metrics are served at /metrics\n".to_string(),
+    ),
+    _ => ("405 Method Not Allowed", "text/plain", String::new()),
+  };
+  write!(
+    &stream,
+    "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+    body.len()
+  )?;
+  Ok(())
+}
+
+/// Serves the latest samples at `/metrics`, one request at a time. Blocks.
+pub fn serve(listener: TcpListener, metrics: Metrics) {
+  for stream in listener.incoming() {
+    if let Err(err) = stream
+      .map_err(Box::from)
+      .and_then(|stream| handle_connection(stream, &metrics))
+    {
+      eprintln!("Exporter: {err}");
+    }
+  }
+}
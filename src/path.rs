@@ -1,3 +1,65 @@
+use std::io;
+use std::path::{Path, PathBuf, absolute};
+
 pub fn get_xdg_dirs() -> xdg::BaseDirectories {
   xdg::BaseDirectories::with_prefix("dogky")
 }
+
+/// The config files of this instance.
+#[derive(Clone)]
+pub struct ConfigPaths {
+  pub config: PathBuf,
+  /// Candidates, in order of preference
+  styles: Vec<PathBuf>,
+}
+
+impl ConfigPaths {
+  /// Explicit paths win over the profile. A profile's styles fall back to `styles.yaml`.
+  pub fn new(config: Option<PathBuf>, styles: Option<PathBuf>, profile: Option<&str>) -> io::Result<Self> {
+    let xdg_dirs = get_xdg_dirs();
+    let profile_file = |name: &str| match profile {
+      Some(profile) => format!("{}.{}.yaml", name, profile),
+      None => format!("{}.yaml", name),
+    };
+    let config = match config {
+      Some(path) => absolute(path)?,
+      None => xdg_dirs.place_config_file(profile_file("dogky"))?,
+    };
+    let styles = match styles {
+      Some(path) => vec![absolute(path)?],
+      None => {
+        let mut styles = vec![xdg_dirs.place_config_file(profile_file("styles"))?];
+        if profile.is_some() {
+          styles.push(xdg_dirs.place_config_file("styles.yaml")?);
+        }
+        styles
+      }
+    };
+    Ok(ConfigPaths { config, styles })
+  }
+
+  /// The first styles file that exists, if any.
+  pub fn styles(&self) -> &Path {
+    self.styles.iter().find(|path| path.exists()).unwrap_or(&self.styles[0])
+  }
+
+  pub fn files(&self) -> impl Iterator<Item = &Path> {
+    std::iter::once(&self.config).chain(&self.styles).map(PathBuf::as_path)
+  }
+}
+
+/// The control socket of the instance with this profile, under `$XDG_RUNTIME_DIR/dogky/`.
+pub fn get_socket_path(profile: Option<&str>) -> io::Result<PathBuf> {
+  get_xdg_dirs().place_runtime_file(match profile {
+    Some(profile) => format!("dogky.{}.sock", profile),
+    None => "dogky.sock".to_string(),
+  })
+}
+
+/// The graph history of the instance with this profile, under `$XDG_STATE_HOME/dogky/`.
+pub fn get_history_path(profile: Option<&str>) -> io::Result<PathBuf> {
+  get_xdg_dirs().place_state_file(match profile {
+    Some(profile) => format!("history.{}.json", profile),
+    None => "history.json".to_string(),
+  })
+}
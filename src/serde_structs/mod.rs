@@ -3,7 +3,7 @@ mod serde_command;
 mod serde_gaps;
 mod serde_regex;
 
-pub use serde_color::SerdeColor;
+pub use serde_color::{PaletteScope, SerdeColor};
 pub use serde_command::SerdeCommand;
 pub use serde_gaps::SerdeGaps;
 pub use serde_regex::SerdeRegex;
@@ -1,12 +1,40 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Deref;
+use std::str::FromStr;
 
-use csscolorparser::Color as CssColor;
+use csscolorparser::{Color as CssColor, ParseColorError};
 use freya_engine::prelude::Color;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Deserializer};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct SerdeColor(Color);
 
+thread_local! {
+  static PALETTE: RefCell<HashMap<String, SerdeColor>> = RefCell::new(HashMap::new());
+}
+
+/// Colors given as `$name` are looked up in the palette set on it, until it's dropped.
+pub struct PaletteScope(());
+
+impl PaletteScope {
+  pub fn enter() -> Self {
+    PaletteScope(())
+  }
+
+  pub fn set(&self, palette: HashMap<String, SerdeColor>) {
+    PALETTE.set(palette);
+  }
+}
+
+impl Drop for PaletteScope {
+  fn drop(&mut self) {
+    PALETTE.take();
+  }
+}
+
 impl Deref for SerdeColor {
   type Target = Color;
 
@@ -15,20 +43,47 @@ impl Deref for SerdeColor {
   }
 }
 
+impl FromStr for SerdeColor {
+  type Err = ParseColorError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let color = s.parse::<CssColor>()?;
+    Ok(SerdeColor(Color::from_argb(
+      (color.a * 255.) as u8,
+      (color.r * 255.) as u8,
+      (color.g * 255.) as u8,
+      (color.b * 255.) as u8,
+    )))
+  }
+}
+
 impl<'de> Deserialize<'de> for SerdeColor {
   fn deserialize<D>(deserializer: D) -> Result<SerdeColor, D::Error>
   where
     D: Deserializer<'de>,
   {
     let s: String = Deserialize::deserialize(deserializer)?;
-    match s.parse::<CssColor>() {
-      Ok(color) => Ok(SerdeColor(Color::from_argb(
-        (color.a * 255.) as u8,
-        (color.r * 255.) as u8,
-        (color.g * 255.) as u8,
-        (color.b * 255.) as u8,
-      ))),
-      Err(_) => Err(serde::de::Error::custom("Invalid color string")),
+    if let Some(name) = s.strip_prefix('$') {
+      return PALETTE
+        .with_borrow(|palette| palette.get(name).cloned())
+        .ok_or_else(|| serde::de::Error::custom(format!("no color named `{}` in the palette", name)));
     }
+    s.parse().map_err(|_| serde::de::Error::custom("Invalid color string"))
+  }
+}
+
+impl JsonSchema for SerdeColor {
+  fn schema_name() -> Cow<'static, str> {
+    "Color".into()
+  }
+
+  fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "string",
+      "format": "color",
+      "description": "A CSS color, e.g. `#8be9fd`, `rgba(0, 0, 0, 0.6)` or `red`. In `styles.yaml`, also `$name` of a \
+        palette color.",
+      "examples": ["#8be9fd", "rgba(0, 0, 0, 0.6)", "$green"],
+    })
   }
 }
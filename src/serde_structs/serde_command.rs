@@ -1,5 +1,7 @@
+use std::borrow::Cow;
 use std::ops::Deref;
 
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Deserializer};
 
 #[derive(Clone, Debug, PartialEq)]
@@ -18,10 +20,24 @@ impl<'de> Deserialize<'de> for SerdeCommand {
   where
     D: Deserializer<'de>,
   {
-    let s: &str = Deserialize::deserialize(deserializer)?;
-    match shlex::split(s) {
+    let s: String = Deserialize::deserialize(deserializer)?;
+    match shlex::split(&s) {
       Some(parts) => Ok(SerdeCommand(parts)),
       None => Err(serde::de::Error::custom("shlex::split()")),
     }
   }
 }
+
+impl JsonSchema for SerdeCommand {
+  fn schema_name() -> Cow<'static, str> {
+    "Command".into()
+  }
+
+  fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "string",
+      "description": "A command and its arguments, split like a shell would. Not run through a shell.",
+      "examples": ["foot htop"],
+    })
+  }
+}
@@ -1,10 +1,13 @@
 use freya::prelude::Gaps;
 
+use std::borrow::Cow;
 use std::ops::Deref;
+use std::str::FromStr;
 
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Deserializer};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct SerdeGaps(Gaps);
 
 impl Deref for SerdeGaps {
@@ -63,15 +66,37 @@ fn parse_gaps(s: &str) -> Result<Gaps, Box<dyn std::error::Error>> {
   }
 }
 
+impl FromStr for SerdeGaps {
+  type Err = Box<dyn std::error::Error>;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    parse_gaps(s).map(SerdeGaps)
+  }
+}
+
 impl<'de> Deserialize<'de> for SerdeGaps {
   fn deserialize<D>(deserializer: D) -> Result<SerdeGaps, D::Error>
   where
     D: Deserializer<'de>,
   {
     let s: String = Deserialize::deserialize(deserializer)?;
-    match parse_gaps(&s) {
-      Ok(gaps) => Ok(SerdeGaps(gaps)),
-      Err(err) => Err(serde::de::Error::custom(err.to_string())),
-    }
+    s.parse()
+      .map_err(|err: Box<dyn std::error::Error>| serde::de::Error::custom(err.to_string()))
+  }
+}
+
+impl JsonSchema for SerdeGaps {
+  fn schema_name() -> Cow<'static, str> {
+    "Gaps".into()
+  }
+
+  fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "string",
+      "pattern": r"^\s*(-?[0-9.]+\s*){1,4}$",
+      "description": "1 to 4 space separated sizes, in CSS order: `all`, `vertical horizontal`, \
+        `top horizontal bottom` or `top right bottom left`",
+      "examples": ["8", "0 0 4 0"],
+    })
   }
 }
@@ -1,6 +1,8 @@
+use std::borrow::Cow;
 use std::ops::Deref;
 
 use regex::Regex;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Deserializer};
 
 #[derive(Clone, Debug)]
@@ -19,7 +21,21 @@ impl<'de> Deserialize<'de> for SerdeRegex {
   where
     D: Deserializer<'de>,
   {
-    let s: &str = Deserialize::deserialize(deserializer)?;
-    Regex::new(s).map(SerdeRegex).map_err(serde::de::Error::custom)
+    let s: String = Deserialize::deserialize(deserializer)?;
+    Regex::new(&s).map(SerdeRegex).map_err(serde::de::Error::custom)
+  }
+}
+
+impl JsonSchema for SerdeRegex {
+  fn schema_name() -> Cow<'static, str> {
+    "Regex".into()
+  }
+
+  fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+      "type": "string",
+      "format": "regex",
+      "description": "A regular expression, in the syntax of the `regex` crate. Unanchored.",
+    })
   }
 }
@@ -1,23 +1,52 @@
 extern crate xdg;
 
-use std::error::Error;
-use std::fs::File;
+use std::collections::HashMap;
+use std::path::Path;
 
+use noyalib::Value;
+use schemars::JsonSchema;
 use serde_derive::Deserialize;
 
-use crate::path::get_xdg_dirs;
-use crate::serde_structs::SerdeColor;
-use crate::serde_structs::SerdeGaps;
+use crate::config_file::{ConfigError, ValueError, merge, read_preprocessed_config_file};
+use crate::path::ConfigPaths;
+use crate::serde_structs::{PaletteScope, SerdeColor, SerdeGaps};
 
-#[derive(Deserialize, Clone)]
+// Every block, and every field within it, is optional. Missing values fall back to the defaults below.
+
+fn color(s: &str) -> SerdeColor {
+  s.parse().unwrap()
+}
+
+fn gaps(s: &str) -> SerdeGaps {
+  s.parse().unwrap()
+}
+
+/// The weather panel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct WeatherStyles {
   pub container_padding: SerdeGaps,
+  /// The weather condition icon
   pub cond_icon_size: f32,
   pub value_color: SerdeColor,
+  /// Around the wind direction arrow
   pub wind_arrow_margin: SerdeGaps,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for WeatherStyles {
+  fn default() -> Self {
+    Self {
+      container_padding: gaps("0 0 4 0"),
+      cond_icon_size: 24.,
+      value_color: color("#8be9fd"),
+      wind_arrow_margin: gaps("0"),
+    }
+  }
+}
+
+/// The machine info panel: `user@host`, distribution, architecture and kernel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct MachineInfoStyles {
   pub user_color: SerdeColor,
   pub at_color: SerdeColor,
@@ -27,42 +56,141 @@ pub struct MachineInfoStyles {
   pub kernel_version_color: SerdeColor,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for MachineInfoStyles {
+  fn default() -> Self {
+    Self {
+      user_color: color("#8be9fd"),
+      at_color: color("#f8f8f2"),
+      host_color: color("#8be9fd"),
+      distro_color: color("#ffb86c"),
+      architecture_color: color("#bd93f9"),
+      kernel_version_color: color("#50fa7b"),
+    }
+  }
+}
+
+/// The CPU, memory and process list panel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct CpuMemoryStyles {
+  /// Values next to their labels, e.g. the frequency
   pub value_color: SerdeColor,
 
+  // Per core usage bars
+  /// Bars in each row of usage bars
   pub bars_per_row: usize,
+  /// Gap between the rows of usage bars
   pub bars_v_gap: f32,
+  /// Height of each usage bar
   pub bar_height: f32,
+  /// Border of the usage bars of performance cores, and of all cores on other CPUs
   pub bar_border_color: SerdeColor,
+  /// Border width of the usage bars of performance cores, and of all cores on other CPUs
   pub bar_border_width: f32,
+  /// Fill of the usage bars of performance cores, and of all cores on other CPUs
   pub bar_fill_color: SerdeColor,
+  // Efficiency cores, on hybrid CPUs
+  /// Border of the usage bars of efficiency cores
   pub bar_efficient_border_color: SerdeColor,
+  /// Border width of the usage bars of efficiency cores
   pub bar_efficient_border_width: f32,
+  /// Fill of the usage bars of efficiency cores
   pub bar_efficient_fill_color: SerdeColor,
+  /// Gap between the usage bars in a row
   pub bar_h_gap: f32,
 
+  // Usage history graphs
+  /// Gap between the CPU and memory graphs
   pub graph_h_gap: f32,
+  /// Height of the CPU and memory graphs
   pub graph_height: f32,
+  /// Border of the CPU graph
   pub graph_cpu_border_color: SerdeColor,
+  /// Border width of the CPU graph
   pub graph_cpu_border_width: f32,
+  /// User time, with system, iowait, irq and steal time stacked on it
   pub graph_cpu_fill_color: SerdeColor,
+  /// System time, stacked on the user time
+  pub graph_cpu_system_fill_color: SerdeColor,
+  /// Time waiting on I/O, stacked on the system time
+  pub graph_cpu_iowait_fill_color: SerdeColor,
+  /// Interrupt time, stacked on the iowait time
+  pub graph_cpu_irq_fill_color: SerdeColor,
+  /// Time taken by the hypervisor, stacked on the irq time
+  pub graph_cpu_steal_fill_color: SerdeColor,
+  /// Border of the memory graph
   pub graph_memory_border_color: SerdeColor,
+  /// Border width of the memory graph
   pub graph_memory_border_width: f32,
+  /// Memory usage, filled
   pub graph_memory_fill_color: SerdeColor,
+  /// Swap usage, drawn as a line over the memory usage
   pub graph_swap_fill_color: SerdeColor,
 
+  // Process list columns
+  /// Width of the PID column
   pub ps_pid_width: f32,
+  /// Width of the CPU usage column
   pub ps_cpu_width: f32,
+  /// Width of the memory usage column
   pub ps_memory_width: f32,
+  /// The column names
   pub ps_header_color: SerdeColor,
+  /// The arrow above the processes sorted by CPU usage
   pub ps_sort_cpu_color: SerdeColor,
+  /// The arrow above the processes sorted by memory usage
   pub ps_sort_memory_color: SerdeColor,
+  /// The processes sorted by CPU usage
   pub ps_cpu_color: SerdeColor,
+  /// The processes sorted by memory usage
   pub ps_memory_color: SerdeColor,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for CpuMemoryStyles {
+  fn default() -> Self {
+    Self {
+      value_color: color("#8be9fd"),
+
+      bars_per_row: 8,
+      bars_v_gap: 4.,
+      bar_height: 8.,
+      bar_border_color: color("#6272a4"),
+      bar_border_width: 1.,
+      bar_fill_color: color("#50fa7b"),
+      bar_efficient_border_color: color("#44475a"),
+      bar_efficient_border_width: 1.,
+      bar_efficient_fill_color: color("#8be9fd"),
+      bar_h_gap: 4.,
+
+      graph_h_gap: 8.,
+      graph_height: 40.,
+      graph_cpu_border_color: color("#6272a4"),
+      graph_cpu_border_width: 1.,
+      graph_cpu_fill_color: color("#50fa7b"),
+      graph_cpu_system_fill_color: color("#ffb86c"),
+      graph_cpu_iowait_fill_color: color("#ff5555"),
+      graph_cpu_irq_fill_color: color("#ff79c6"),
+      graph_cpu_steal_fill_color: color("#8be9fd"),
+      graph_memory_border_color: color("#6272a4"),
+      graph_memory_border_width: 1.,
+      graph_memory_fill_color: color("#bd93f9"),
+      graph_swap_fill_color: color("#ff79c6"),
+
+      ps_pid_width: 56.,
+      ps_cpu_width: 48.,
+      ps_memory_width: 72.,
+      ps_header_color: color("#f8f8f2"),
+      ps_sort_cpu_color: color("#50fa7b"),
+      ps_sort_memory_color: color("#bd93f9"),
+      ps_cpu_color: color("#50fa7b"),
+      ps_memory_color: color("#bd93f9"),
+    }
+  }
+}
+
+/// The disk panel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct DiskStyles {
   pub name_color: SerdeColor,
   pub value_color: SerdeColor,
@@ -72,14 +200,42 @@ pub struct DiskStyles {
   pub bar_fill_color: SerdeColor,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for DiskStyles {
+  fn default() -> Self {
+    Self {
+      name_color: color("#ffb86c"),
+      value_color: color("#8be9fd"),
+      bar_height: 8.,
+      bar_border_color: color("#6272a4"),
+      bar_border_width: 1.,
+      bar_fill_color: color("#ffb86c"),
+    }
+  }
+}
+
+/// The GPU panel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct GpuStyles {
   pub name_color: SerdeColor,
+  /// Labels, e.g. `Memory`
   pub usage_name_color: SerdeColor,
   pub value_color: SerdeColor,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for GpuStyles {
+  fn default() -> Self {
+    Self {
+      name_color: color("#ffb86c"),
+      usage_name_color: color("#f8f8f2"),
+      value_color: color("#8be9fd"),
+    }
+  }
+}
+
+/// The network panel.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct NetworkStyles {
   pub name_color: SerdeColor,
   pub value_color: SerdeColor,
@@ -94,15 +250,67 @@ pub struct NetworkStyles {
   pub graph_upload_fill_color: SerdeColor,
 }
 
-#[derive(Deserialize, Clone)]
+impl Default for NetworkStyles {
+  fn default() -> Self {
+    Self {
+      name_color: color("#f8f8f2"),
+      value_color: color("#8be9fd"),
+
+      graph_h_gap: 8.,
+      graph_height: 40.,
+      graph_download_border_color: color("#6272a4"),
+      graph_download_border_width: 1.,
+      graph_download_fill_color: color("#50fa7b"),
+      graph_upload_border_color: color("#6272a4"),
+      graph_upload_border_width: 1.,
+      graph_upload_fill_color: color("#ff79c6"),
+    }
+  }
+}
+
+/// A built-in set of colors.
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+  /// The default colors
+  Dracula,
+  Nord,
+  GruvboxDark,
+  CatppuccinMocha,
+}
+
+impl Theme {
+  fn source(self) -> &'static str {
+    match self {
+      Theme::Dracula => include_str!("themes/dracula.yaml"),
+      Theme::Nord => include_str!("themes/nord.yaml"),
+      Theme::GruvboxDark => include_str!("themes/gruvbox_dark.yaml"),
+      Theme::CatppuccinMocha => include_str!("themes/catppuccin_mocha.yaml"),
+    }
+  }
+}
+
+/// `styles.yaml`. Sizes are in pixels.
+#[derive(Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct StylesConfig {
+  /// Merged under the rest of the file, palette included
+  pub theme: Option<Theme>,
+  /// Named colors. Any color can be given as `$name` instead, palette colors included.
+  pub palette: HashMap<String, SerdeColor>,
+
+  /// Window width
   pub width: u32,
   pub background_color: SerdeColor,
+  /// A font family. Only read at startup.
   pub font: String,
   pub text_size: f32,
   pub text_color: SerdeColor,
+  /// Config and panel errors shown in the window
+  pub error_color: SerdeColor,
   pub padding: SerdeGaps,
   pub separator_height: f32,
+  /// Gap between side by side items
   pub h_gap: f32,
 
   pub weather: WeatherStyles,
@@ -113,15 +321,154 @@ pub struct StylesConfig {
   pub network: NetworkStyles,
 }
 
-pub fn load_config() -> Result<StylesConfig, Box<dyn Error>> {
-  let config_path = get_xdg_dirs().place_config_file("styles.yaml")?;
-  let config_file = File::open(config_path)?;
-  let config_styles: StylesConfig = noyalib::from_reader(config_file)?;
-  Ok(config_styles)
+impl Default for StylesConfig {
+  fn default() -> Self {
+    Self {
+      theme: None,
+      palette: HashMap::new(),
+
+      width: 330,
+      background_color: color("rgba(0, 0, 0, 0.6)"),
+      font: "DejaVu Sans Mono".to_string(),
+      text_size: 12.,
+      text_color: color("#f8f8f2"),
+      error_color: color("#ff5555"),
+      padding: gaps("8"),
+      separator_height: 16.,
+      h_gap: 8.,
+
+      weather: WeatherStyles::default(),
+      machine_info: MachineInfoStyles::default(),
+      cpu_memory: CpuMemoryStyles::default(),
+      disk: DiskStyles::default(),
+      gpu: GpuStyles::default(),
+      network: NetworkStyles::default(),
+    }
+  }
+}
+
+fn value_error(key_path: &str, message: impl std::fmt::Display) -> ValueError {
+  ValueError {
+    key_path: key_path.to_string(),
+    message: format!("{}: {}", key_path, message),
+  }
+}
+
+/// Merges the theme under the file.
+fn apply_theme(value: &mut Value) -> Result<(), ValueError> {
+  if let Some(theme) = value.get("theme") {
+    let theme: Theme = noyalib::from_value(theme).map_err(|err| value_error("theme", err))?;
+    let mut themed: Value = noyalib::from_str(theme.source()).unwrap();
+    merge(&mut themed, std::mem::take(value));
+    *value = themed;
+  }
+  Ok(())
+}
+
+/// Reads the palette, following entries given as `$name` of another entry.
+fn read_palette(value: &Value) -> Result<HashMap<String, SerdeColor>, ValueError> {
+  let entries: HashMap<String, String> = match value.get("palette") {
+    Some(palette) => noyalib::from_value(palette).map_err(|err| value_error("palette", err))?,
+    None => HashMap::new(),
+  };
+  let mut palette = HashMap::new();
+  for name in entries.keys() {
+    let key_path = format!("palette.{}", name);
+    let mut entry = &entries[name];
+    // A chain longer than the palette goes around in a loop
+    for _ in 0..=entries.len() {
+      let Some(next_name) = entry.strip_prefix('$') else {
+        break;
+      };
+      entry = entries
+        .get(next_name)
+        .ok_or_else(|| value_error(&key_path, format!("no color named `{}` in the palette", next_name)))?;
+    }
+    if entry.starts_with('$') {
+      return Err(value_error(&key_path, "the palette refers to itself in a loop"));
+    }
+    let color = entry
+      .parse()
+      .map_err(|_| value_error(&key_path, "Invalid color string"))?;
+    palette.insert(name.clone(), color);
+  }
+  Ok(palette)
+}
+
+/// Colors given as `$name` are looked up in the palette, theme included
+fn read_styles_file(path: &Path) -> Result<StylesConfig, ConfigError> {
+  let palette_scope = PaletteScope::enter();
+  read_preprocessed_config_file(path, |value| {
+    apply_theme(value)?;
+    palette_scope.set(read_palette(value)?);
+    Ok(())
+  })
+}
+
+pub fn load_config(paths: &ConfigPaths) -> Result<StylesConfig, ConfigError> {
+  let config_path = paths.styles();
+  if !config_path.exists() {
+    return Ok(StylesConfig::default());
+  }
+  read_styles_file(config_path)
 }
 
 #[derive(Clone)]
 pub struct GlobalStyles {
   pub container_width: f32,
   pub h_gap: f32,
+  pub separator_height: f32,
+  pub error_color: SerdeColor,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::cli::DEFAULT_STYLES;
+
+  #[test]
+  fn default_styles_file_matches_defaults() {
+    let styles: StylesConfig = noyalib::from_str(DEFAULT_STYLES).unwrap();
+    assert_eq!(styles, StylesConfig::default());
+  }
+
+  fn read_styles(name: &str, source: &str) -> Result<StylesConfig, ConfigError> {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(name);
+    std::fs::write(&path, source).unwrap();
+    read_styles_file(&path)
+  }
+
+  #[test]
+  fn palette_names_are_resolved_in_color_fields_only() {
+    let styles = read_styles(
+      "palette.yaml",
+      "palette:\n  green: \"#50fa7b\"\n  accent: $green\ntext_color: $accent\nfont: $green\n",
+    )
+    .unwrap();
+    assert_eq!(styles.text_color, color("#50fa7b"));
+    assert_eq!(styles.palette["accent"], color("#50fa7b"));
+    assert_eq!(styles.font, "$green");
+  }
+
+  #[test]
+  fn unknown_palette_names_are_errors_at_the_color() {
+    let err = read_styles("unknown-color.yaml", "text_color: $missing\n").unwrap_err();
+    assert!(err.message.contains("no color named `missing`"), "{}", err);
+    assert_eq!(err.location.unwrap().line(), 1);
+  }
+
+  #[test]
+  fn palette_loops_are_errors() {
+    let err = read_styles("palette-loop.yaml", "palette:\n  a: $b\n  b: $a\n").unwrap_err();
+    assert!(err.message.contains("loop"), "{}", err);
+  }
+
+  #[test]
+  fn themes_load() {
+    for theme in ["dracula", "nord", "gruvbox_dark", "catppuccin_mocha"] {
+      let styles = read_styles(&format!("{}.yaml", theme), &format!("theme: {}\n", theme));
+      assert!(styles.is_ok(), "{}: {}", theme, styles.unwrap_err());
+    }
+  }
 }
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 
@@ -45,3 +47,27 @@ pub fn substitute_env_vars(command: &str) -> String {
     })
     .into_owned()
 }
+
+/// Replaces a leading `~` with the home directory, like a shell would
+pub fn expand_home(path: &str) -> PathBuf {
+  let home = std::env::var_os("HOME").map(PathBuf::from);
+  match (path.strip_prefix('~'), home) {
+    (Some(""), Some(home)) => home,
+    (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+    _ => PathBuf::from(path),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn expands_a_leading_tilde() {
+    let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+    assert_eq!(expand_home("~"), home);
+    assert_eq!(expand_home("~/.secrets/key"), home.join(".secrets/key"));
+    assert_eq!(expand_home("~user/key"), PathBuf::from("~user/key"));
+    assert_eq!(expand_home("/etc/~/key"), PathBuf::from("/etc/~/key"));
+  }
+}